@@ -0,0 +1,204 @@
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+// what of a filetype's syntax hides brackets from matching
+struct Syntax {
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    // `'` starts a character literal, and anything else that isn't one (a rust lifetime) is skipped
+    char_literals: bool,
+}
+
+fn syntax(filetype: &str) -> Option<Syntax> {
+    let (line_comment, block_comment, quotes, char_literals) = match filetype {
+        "rust" | "c" | "java" => (Some("//"), Some(("/*", "*/")), &['"'][..], true),
+        "go" => (Some("//"), Some(("/*", "*/")), &['"', '`'][..], true),
+        "javascript" => (Some("//"), Some(("/*", "*/")), &['"', '\'', '`'][..], false),
+        "python" | "toml" | "yaml" => (Some("#"), None, &['"', '\''][..], false),
+        "json" => (None, None, &['"'][..], false),
+        "html" => (None, Some(("<!--", "-->")), &[][..], false),
+        _ => return None,
+    };
+
+    Some(Syntax {
+        line_comment,
+        block_comment,
+        quotes,
+        char_literals,
+    })
+}
+
+// characters typed in insert mode that also get their closer, `'` is left out where it is
+// mostly an apostrophe or a lifetime
+pub fn default_auto_pairs(filetype: &str) -> &'static str {
+    match filetype {
+        "text" | "markdown" | "rust" => "([{\"",
+        "javascript" | "go" => "([{\"'`",
+        _ => "([{\"'",
+    }
+}
+
+// the closer of an opener `autopairs` can pair, a quote closes itself
+pub fn closer(opener: char) -> Option<char> {
+    match opener {
+        '"' | '\'' | '`' => Some(opener),
+        _ => PAIRS
+            .iter()
+            .find(|(open, _)| *open == opener)
+            .map(|(_, close)| *close),
+    }
+}
+
+fn is_bracket(ch: char) -> bool {
+    PAIRS
+        .iter()
+        .any(|(open, close)| ch == *open || ch == *close)
+}
+
+// what a row starts inside of, left open by the rows before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum State {
+    #[default]
+    Code,
+    BlockComment,
+    Quote(char),
+}
+
+// byte offsets and characters of the brackets in a row starting in `state`, leaving out those
+// in strings and comments when the filetype is known, and the state the next row starts in
+pub fn row_brackets(row: &str, filetype: &str, mut state: State) -> (Vec<(usize, char)>, State) {
+    let syntax = match syntax(filetype) {
+        Some(syntax) => syntax,
+        None => {
+            let brackets = row.char_indices().filter(|(_, ch)| is_bracket(*ch));
+            return (brackets.collect(), State::Code);
+        }
+    };
+
+    let mut brackets = vec![];
+    let mut i = 0;
+
+    while i < row.len() {
+        let rest = &row[i..];
+
+        match state {
+            State::BlockComment => {
+                let (_, end) = syntax.block_comment.unwrap_or_default();
+                match rest.find(end) {
+                    Some(j) => {
+                        i += j + end.len();
+                        state = State::Code;
+                    }
+                    None => i = row.len(),
+                }
+            }
+            State::Quote(quote) => match string_end(rest, quote) {
+                Some(j) => {
+                    i += j;
+                    state = State::Code;
+                }
+                None => i = row.len(),
+            },
+            State::Code => {
+                let ch = rest.chars().next().unwrap_or_default();
+
+                if syntax
+                    .line_comment
+                    .is_some_and(|start| rest.starts_with(start))
+                {
+                    break;
+                } else if let Some((start, _)) = syntax
+                    .block_comment
+                    .filter(|(start, _)| rest.starts_with(start))
+                {
+                    i += start.len();
+                    state = State::BlockComment;
+                } else if syntax.quotes.contains(&ch) {
+                    i += 1;
+                    state = State::Quote(ch);
+                } else if syntax.char_literals && ch == '\'' {
+                    i += char_literal_len(rest).unwrap_or(1);
+                } else {
+                    if is_bracket(ch) {
+                        brackets.push((i, ch));
+                    }
+                    i += ch.len_utf8();
+                }
+            }
+        }
+    }
+
+    // an unclosed string ends with its line except for `` ` ``
+    if matches!(state, State::Quote(quote) if quote != '`') {
+        state = State::Code;
+    }
+
+    (brackets, state)
+}
+
+// length up to past the `quote` closing the string `rest` is inside of
+fn string_end(rest: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+
+    for (i, ch) in rest.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if ch == quote => return Some(i + ch.len_utf8()),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// length of a character literal such as `'a'` or `'\n'` starting `rest`
+fn char_literal_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+
+    match chars.next()? {
+        // past the backslash and the character it escapes
+        (_, '\\') => rest
+            .get(3..)?
+            .char_indices()
+            .take(10)
+            .find(|(_, ch)| *ch == '\'')
+            .map(|(j, _)| 3 + j + 1),
+        (_, '\'' | '\n') => None,
+        _ => match chars.next()? {
+            (j, '\'') => Some(j + 1),
+            _ => None,
+        },
+    }
+}
+
+// the bracket pairing with `ch` and whether it comes after it
+pub fn partner(ch: char) -> Option<(char, bool)> {
+    PAIRS.iter().find_map(|(open, close)| match ch {
+        _ if ch == *open => Some((*close, true)),
+        _ if ch == *close => Some((*open, false)),
+        _ => None,
+    })
+}
+
+// the first `to` not balanced by a `from` before it
+pub fn depth_match<T>(
+    brackets: impl Iterator<Item = (T, char)>,
+    from: char,
+    to: char,
+) -> Option<T> {
+    let mut depth = 0;
+
+    for (at, ch) in brackets {
+        if ch == from {
+            depth += 1;
+        } else if ch == to {
+            if depth == 0 {
+                return Some(at);
+            }
+            depth -= 1;
+        }
+    }
+
+    None
+}
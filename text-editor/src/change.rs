@@ -0,0 +1,40 @@
+use crate::keymap::Key;
+
+// normal actions that change the buffer, the ones `.` repeats
+pub const CHANGE_ACTIONS: &[&str] = &[
+    "insert",
+    "replace_mode",
+    "append",
+    "open_below",
+    "open_above",
+    "delete_line",
+    "join",
+    "delete_char",
+    "replace_char",
+    "toggle_case",
+    "put_after",
+    "put_before",
+    "delete",
+    "change",
+];
+
+// a change as it was made, replayed by `.`: the action and its count, the characters typed
+// after it (`r`'s replacement, `iw` after `d`) and the keys typed in insert mode until `<Esc>`
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub action: String,
+    pub count: usize,
+    pub chars: Vec<char>,
+    pub typed: Vec<Key>,
+}
+
+impl Change {
+    pub fn new(action: &str, count: usize) -> Change {
+        Change {
+            action: action.to_string(),
+            count,
+            chars: vec![],
+            typed: vec![],
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::{fs::read_dir, path::Path};
+
+use serde_json::Value;
+
+use crate::{
+    editor::Editor,
+    mode_handlers::command::{command_key, command_names},
+    options::{OptionKind, OPTIONS},
+    range,
+};
+
+// matches for the word before the cursor on the command line, cycled with Tab
+#[derive(Debug, Clone)]
+pub struct Completion {
+    // byte index in the command line (without the `:`) where the completed word starts
+    pub start: usize,
+    // the word as typed, shown again after the last candidate
+    pub typed: String,
+    pub candidates: Vec<String>,
+    pub selected: Option<usize>,
+}
+
+impl Completion {
+    // `line` is the command line up to the cursor
+    pub fn new(line: &str, config: &Value, tabs: &[Editor]) -> Completion {
+        // only the last of several `|` chained commands
+        let segment = line.rfind('|').map(|i| i + 1).unwrap_or(0);
+        let segment = segment + (line[segment..].len() - line[segment..].trim_start().len());
+        // and after its range, like `:0r`
+        let (_, command) = range::parse(&line[segment..], 1, 1);
+        let segment = line.len() - command.len();
+
+        let (start, candidates) = match command.split_once(' ') {
+            None => (segment, matching(command_names(config), command)),
+            Some((name, args)) => {
+                let args_start = line.len() - args.len();
+
+                match command_key(config, name) {
+                    Some("edit_file" | "new_tab") => {
+                        let mut candidates = paths(args);
+                        for tab in tabs.iter() {
+                            if tab.filepath.starts_with(args) && !candidates.contains(&tab.filepath)
+                            {
+                                candidates.push(tab.filepath.clone());
+                            }
+                        }
+                        (args_start, candidates)
+                    }
+                    Some("write" | "force_write" | "save_as") => (args_start, paths(args)),
+                    Some("read") if !args.starts_with('!') => (args_start, paths(args)),
+                    Some("set" | "set_local" | "set_global") => {
+                        let word = args.rsplit(' ').next().unwrap_or(args);
+                        (line.len() - word.len(), option_names(word))
+                    }
+                    _ => (line.len(), vec![]),
+                }
+            }
+        };
+
+        Completion {
+            start,
+            typed: line[start..].to_string(),
+            candidates,
+            selected: None,
+        }
+    }
+
+    // the text to put in place of the word, `forward` for Tab and not for Shift-Tab
+    pub fn cycle(&mut self, forward: bool) -> &str {
+        let count = self.candidates.len();
+
+        self.selected = match (self.selected, forward) {
+            (None, true) => Some(0),
+            (None, false) => count.checked_sub(1),
+            (Some(i), true) if i + 1 < count => Some(i + 1),
+            (Some(i), false) if i > 0 => Some(i - 1),
+            _ => None,
+        };
+
+        match self.selected {
+            Some(i) => self.candidates[i].as_str(),
+            None => self.typed.as_str(),
+        }
+    }
+}
+
+fn matching(mut names: Vec<String>, typed: &str) -> Vec<String> {
+    names.retain(|name| name.starts_with(typed));
+    names.sort();
+    names.dedup();
+    names
+}
+
+// entries of the directory part of `typed`, directories ending in `/`
+fn paths(typed: &str) -> Vec<String> {
+    let (dir, name) = match typed.rfind('/') {
+        Some(i) => (&typed[..i + 1], &typed[i + 1..]),
+        None => ("", typed),
+    };
+
+    let entries = match read_dir(if dir.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(dir)
+    }) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+
+            // hidden files only when asked for
+            if !file_name.starts_with(name)
+                || (file_name.starts_with('.') && !name.starts_with('.'))
+            {
+                return None;
+            }
+
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, file_name, slash))
+        })
+        .collect();
+
+    candidates.sort();
+    candidates
+}
+
+// option names and short names, with `no`/`inv` forms of booleans once such a prefix is typed
+fn option_names(typed: &str) -> Vec<String> {
+    // nothing to offer for a value
+    if typed.contains(['=', ':']) {
+        return vec![];
+    }
+
+    let prefixed = typed.starts_with("no") || typed.starts_with("inv");
+    let mut names = vec![];
+
+    for def in OPTIONS.iter() {
+        for name in [def.name, def.short] {
+            names.push(name.to_string());
+
+            if prefixed && def.kind == OptionKind::Bool {
+                names.push(format!("no{}", name));
+                names.push(format!("inv{}", name));
+            }
+        }
+    }
+
+    matching(names, typed)
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::read_to_string,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{Map, Value};
+
+// project-local config, looked up from the opened file towards the root
+pub const PROJECT_CONFIG: &str = ".kass.toml";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub settings: Value,
+    // dotted key (e.g. `command_mode.quit`) -> file the effective value came from
+    pub sources: BTreeMap<String, String>,
+    // the project config merged in, if any
+    pub project: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            settings: Value::Object(Map::new()),
+            sources: BTreeMap::new(),
+            project: None,
+        }
+    }
+
+    // user config first, then the project config merged over it
+    pub fn load(config_dir: &Path, filepath: &Path) -> Result<Config> {
+        let mut layers = vec![];
+        let project = Self::project_config(filepath);
+
+        if let Some(path) = Self::user_config(config_dir) {
+            layers.push(path);
+        }
+        if let Some(path) = project.clone() {
+            layers.push(path);
+        }
+
+        if layers.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No config.toml or config.json found in {}",
+                    config_dir.display()
+                ),
+            ));
+        }
+
+        let mut config = Config::new();
+
+        for path in layers {
+            let settings = Self::read_file(&path)?;
+            config.merge(settings, path.display().to_string().as_str());
+        }
+        config.project = project;
+
+        Ok(config)
+    }
+
+    fn user_config(config_dir: &Path) -> Option<PathBuf> {
+        ["config.toml", "config.json"]
+            .iter()
+            .map(|name| config_dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    pub fn project_config(filepath: &Path) -> Option<PathBuf> {
+        let filepath = env::current_dir()
+            .map(|dir| dir.join(filepath))
+            .unwrap_or_else(|_| filepath.to_path_buf());
+
+        filepath
+            .ancestors()
+            .skip(1)
+            .map(|dir| dir.join(PROJECT_CONFIG))
+            .find(|path| path.is_file())
+    }
+
+    fn read_file(path: &Path) -> Result<Value> {
+        let content = read_to_string(path)?;
+        let invalid =
+            |e: String| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e));
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => {
+                let parsed: toml::Value =
+                    toml::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+                serde_json::to_value(parsed).map_err(|e| invalid(e.to_string()))
+            }
+            _ => serde_json::from_str(&content).map_err(|e| invalid(e.to_string())),
+        }
+    }
+
+    pub fn merge(&mut self, overlay: Value, source: &str) {
+        merge_value(&mut self.settings, overlay, "", source, &mut self.sources);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        key.split('.')
+            .try_fold(&self.settings, |value, part| value.get(part))
+    }
+
+    // `key=value (source)` for every leaf setting
+    pub fn describe(&self) -> Vec<String> {
+        self.sources
+            .iter()
+            .filter_map(|(key, source)| {
+                self.get(key)
+                    .map(|value| format!("{}={} ({})", key, value, source))
+            })
+            .collect()
+    }
+}
+
+fn merge_value(
+    base: &mut Value,
+    overlay: Value,
+    prefix: &str,
+    source: &str,
+    sources: &mut BTreeMap<String, String>,
+) {
+    if let (Value::Object(base), Value::Object(overlay)) = (base, overlay) {
+        for (key, value) in overlay {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+
+            match base.get_mut(&key) {
+                Some(existing) if existing.is_object() && value.is_object() => {
+                    merge_value(existing, value, path.as_str(), source, sources);
+                }
+                _ => {
+                    let nested = format!("{}.", path);
+                    sources.retain(|k, _| *k != path && !k.starts_with(nested.as_str()));
+                    record_sources(&value, path.as_str(), source, sources);
+                    base.insert(key, value);
+                }
+            }
+        }
+    }
+}
+
+fn record_sources(value: &Value, path: &str, source: &str, sources: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                record_sources(value, format!("{}.{}", path, key).as_str(), source, sources);
+            }
+        }
+        _ => {
+            sources.insert(path.to_string(), source.to_string());
+        }
+    }
+}
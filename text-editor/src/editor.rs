@@ -1,10 +1,24 @@
 use std::{
-    fs::{read_to_string, OpenOptions},
-    io::{prelude::*, Result},
-    path::Path,
+    cell::{Ref, RefCell},
+    collections::HashMap,
+    fs::{read, OpenOptions},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{prelude::*, Error, ErrorKind, Result},
+    ops::Range,
+    path::{Path, PathBuf},
 };
 
-use crate::position::Position;
+use crate::{
+    brackets,
+    config::Config,
+    editorconfig,
+    enums::{BufferKind, ScreenRow},
+    marks::{self, Mark},
+    options::{self, OptionValue, Options},
+    position::Position,
+    register::Register,
+    text_object::{self, TextObject},
+};
 
 #[derive(Debug, Clone)]
 pub struct Bound {
@@ -12,6 +26,159 @@ pub struct Bound {
     pub x2: u16,
 }
 
+// columns `text` takes on screen, with tabs reaching the next multiple of `tabstop`
+pub fn display_width(text: &str, tabstop: usize) -> usize {
+    let tabstop = tabstop.max(1);
+
+    text.chars().fold(0, |col, ch| match ch {
+        '\t' => col + tabstop - col % tabstop,
+        _ => col + 1,
+    })
+}
+
+pub fn expand_tabs(text: &str, tabstop: usize) -> String {
+    let mut expanded = String::new();
+
+    for ch in text.chars() {
+        match ch {
+            '\t' => {
+                let width = display_width(expanded.as_str(), tabstop);
+                expanded.push_str(" ".repeat(tabstop.max(1) - width % tabstop.max(1)).as_str());
+            }
+            ch => expanded.push(ch),
+        }
+    }
+
+    expanded
+}
+
+const INDENT_CLOSERS: [char; 3] = ['}', ')', ']'];
+
+// rows either way of a bracket that are searched for its match, so that showing the match on
+// every redraw doesn't go through a whole long file
+const MATCH_ROWS: usize = 500;
+
+// indentation settings of a tab, resolved from the options by `Kass::indent`
+#[derive(Debug, Clone, Copy)]
+pub struct Indent {
+    pub auto: bool,
+    pub smart: bool,
+    pub shiftwidth: usize,
+    pub tabstop: usize,
+    pub expandtab: bool,
+}
+
+impl Indent {
+    // whitespace reaching `width` columns, tabs first unless expandtab is set
+    pub fn whitespace(&self, width: usize) -> String {
+        if self.expandtab {
+            " ".repeat(width)
+        } else {
+            let tabstop = self.tabstop.max(1);
+            "\t".repeat(width / tabstop) + " ".repeat(width % tabstop).as_str()
+        }
+    }
+}
+
+// how a tab is written to disk, resolved from the options by `Kass::file_format`
+#[derive(Debug, Clone)]
+pub struct FileFormat {
+    pub line_ending: &'static str,
+    pub encoding: String,
+    pub trim_trailing_whitespace: bool,
+    pub final_newline: bool,
+}
+
+// byte order marks pick the encoding, anything that isn't UTF-8 is read as latin1; the text
+// comes with the `fileencoding` it was read as
+fn decode(bytes: Vec<u8>) -> (String, &'static str) {
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| from([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    match bytes.as_slice() {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => (String::from_utf8_lossy(rest).to_string(), "utf-8-bom"),
+        [0xFF, 0xFE, rest @ ..] => (utf16(rest, u16::from_le_bytes), "utf-16le"),
+        [0xFE, 0xFF, rest @ ..] => (utf16(rest, u16::from_be_bytes), "utf-16be"),
+        _ => match String::from_utf8(bytes) {
+            Ok(content) => (content, "utf-8"),
+            Err(e) => (
+                e.into_bytes().iter().map(|byte| *byte as char).collect(),
+                "latin1",
+            ),
+        },
+    }
+}
+
+fn encode(content: &str, encoding: &str) -> Result<Vec<u8>> {
+    match encoding {
+        "utf-8-bom" => Ok([&[0xEF, 0xBB, 0xBF], content.as_bytes()].concat()),
+        "utf-16le" => Ok([0xFF, 0xFE]
+            .into_iter()
+            .chain(content.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect()),
+        "utf-16be" => Ok([0xFE, 0xFF]
+            .into_iter()
+            .chain(content.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect()),
+        "latin1" => content
+            .chars()
+            .map(u8::try_from)
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Text can't be saved as latin1")),
+        _ => Ok(content.as_bytes().to_vec()),
+    }
+}
+
+// a file read as anything but plain UTF-8 is saved the same way, over what .editorconfig says
+fn keep_encoding(options: &mut Options, encoding: &str) {
+    if encoding != "utf-8" {
+        let def = options::find("fileencoding").expect("unknown option");
+        options.set(def, OptionValue::Text(encoding.to_string()));
+    }
+}
+
+fn rows_hash(rows: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    rows.hash(&mut hasher);
+    hasher.finish()
+}
+
+// byte of the first character of `row` that reaches past screen column `cell`
+fn byte_at_cell(row: &str, cell: usize, tabstop: usize) -> Option<usize> {
+    row.char_indices()
+        .find(|(i, ch)| display_width(&row[..i + ch.len_utf8()], tabstop) > cell)
+        .map(|(i, _)| i)
+}
+
+// `col` within `row` and back on the start of a character, for a cursor whose row changed
+// under it
+fn char_boundary(row: &str, col: usize) -> usize {
+    let mut col = col.min(row.len());
+    while !row.is_char_boundary(col) {
+        col -= 1;
+    }
+    col
+}
+
+fn leading_whitespace(row: &str) -> &str {
+    &row[..row.len() - row.trim_start().len()]
+}
+
+// the rows the last `replace_rows` took out, put back by `u` as long as nothing else changed
+#[derive(Debug, Clone)]
+struct Undo {
+    start: usize,
+    rows: Vec<String>,
+    added: usize,
+    // hash of the rows right after the change
+    after: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct Editor {
     pub rows: Vec<String>,
@@ -22,31 +189,64 @@ pub struct Editor {
     pub bounds: (Bound, Bound),
     pub editor_size: Position,
     pub title: String,
+    // buffer-local overrides of the global options
+    pub options: Options,
+    // the .kass.toml that applies to the file
+    pub project_config: Option<PathBuf>,
+    // what went wrong opening the file without stopping it, for the main loop to show
+    pub warnings: Vec<String>,
+    // hash of the rows as last read or written, to tell whether there are unsaved changes
+    saved: u64,
+    pub kind: BufferKind,
+    pub marks: HashMap<char, Mark>,
+    // positions left by big jumps, `jump_index` is where `Ctrl-o` and `Ctrl-i` are in them
+    pub jumps: Vec<Mark>,
+    pub jump_index: usize,
+    // cursors besides the main one, their `col` is what `cursor.x` would be
+    pub extra_cursors: Vec<Mark>,
+    // where `Ctrl-v` started a visual block, the cursor is its other corner
+    pub block_start: Option<Mark>,
+    undo: Option<Undo>,
+    // the bracket scanner state each row starts in, with the hash of the rows and filetype it
+    // was worked out for
+    row_states: RefCell<(Option<u64>, Vec<brackets::State>)>,
 }
 
 impl Editor {
-    fn file_to_rows(filepath: String) -> Result<Vec<String>> {
+    // the file's lines and the encoding they were read as, utf-8 for a file that doesn't exist
+    pub fn file_to_rows(filepath: String) -> Result<(Vec<String>, &'static str)> {
         let mut rows: Vec<String> = vec![String::new()];
+        let mut encoding = "utf-8";
 
         if Path::new(filepath.as_str()).is_file() {
-            let content = read_to_string(filepath)?;
+            let content;
+            (content, encoding) = decode(read(filepath)?);
 
-            rows = content.lines().map(String::from).collect();
+            // files with only CR line endings
+            rows = if !content.contains('\n') && content.contains('\r') {
+                content.split_terminator('\r').map(String::from).collect()
+            } else {
+                content.lines().map(String::from).collect()
+            };
         }
 
-        Ok(rows)
+        Ok((rows, encoding))
     }
 
-    fn rows_to_file(rows: Vec<String>) -> String {
-        let content = rows.join("\n");
+    pub fn rows_to_file(rows: Vec<String>, format: &FileFormat) -> String {
+        let mut content = rows.join(format.line_ending);
+
+        if format.final_newline {
+            content.push_str(format.line_ending);
+        }
 
         content
     }
 
     pub fn new(filepath: String) -> Result<Editor> {
-        let mut rows = Self::file_to_rows(filepath.clone())?;
+        let (mut rows, encoding) = Self::file_to_rows(filepath.clone())?;
 
-        if rows.len() == 0 {
+        if rows.is_empty() {
             rows.push(String::new())
         }
 
@@ -56,6 +256,10 @@ impl Editor {
         };
 
         let title = file_name;
+        let (mut options, warnings) = editorconfig::options_for(filepath.as_str());
+        keep_encoding(&mut options, encoding);
+        let project_config = Config::project_config(Path::new(&filepath));
+        let saved = rows_hash(&rows);
 
         Ok(Editor {
             rows,
@@ -66,40 +270,131 @@ impl Editor {
             bounds: (Bound { x1: 0, x2: 0 }, Bound { x1: 0, x2: 0 }),
             editor_size: Position::new(),
             title,
+            options,
+            project_config,
+            warnings,
+            saved,
+            kind: BufferKind::File,
+            marks: HashMap::new(),
+            jumps: vec![],
+            jump_index: 0,
+            extra_cursors: vec![],
+            block_start: None,
+            undo: None,
+            row_states: RefCell::default(),
         })
     }
 
+    // a tab not backed by a file
+    pub fn scratch(title: &str, rows: Vec<String>, kind: BufferKind) -> Editor {
+        Editor {
+            rows: if rows.is_empty() {
+                vec![String::new()]
+            } else {
+                rows
+            },
+            filepath: String::new(),
+            cursor: Position::new(),
+            coloff: 0,
+            rowoff: 0,
+            bounds: (Bound { x1: 0, x2: 0 }, Bound { x1: 0, x2: 0 }),
+            editor_size: Position::new(),
+            title: title.to_string(),
+            options: Options::default(),
+            project_config: None,
+            warnings: vec![],
+            saved: 0,
+            kind,
+            marks: HashMap::new(),
+            jumps: vec![],
+            jump_index: 0,
+            extra_cursors: vec![],
+            block_start: None,
+            undo: None,
+            row_states: RefCell::default(),
+        }
+    }
+
+    // no changes since the file was read or written, which a file changed on disk doesn't undo
     pub fn is_saved(&self) -> bool {
-        let rows = match Self::file_to_rows(self.filepath.clone()) {
-            Ok(rows) => Some(rows),
-            Err(_) => None,
-        };
+        self.kind != BufferKind::File || rows_hash(&self.rows) == self.saved
+    }
 
-        if let Some(rows) = rows {
-            if self.rows.clone() == rows {
-                true
-            } else {
-                false
+    // the rows are what the file holds now
+    pub fn mark_saved(&mut self) {
+        self.saved = rows_hash(&self.rows);
+    }
+
+    pub fn save(&mut self, format: &FileFormat) -> Result<()> {
+        if self.kind != BufferKind::File {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("{} is not a file", self.title),
+            ));
+        }
+
+        let filepath = self.filepath.clone();
+        self.write_to(filepath.as_str(), format, false)?;
+        self.mark_saved();
+
+        Ok(())
+    }
+
+    // writes the buffer to `filepath`, after what is already there with `append`
+    pub fn write_to(&mut self, filepath: &str, format: &FileFormat, append: bool) -> Result<()> {
+        if format.trim_trailing_whitespace {
+            for row in self.rows.iter_mut() {
+                row.truncate(row.trim_end().len());
             }
+            let row_idx = (self.cursor.y + self.rowoff) as usize;
+            self.cursor.x = self.cursor.x.min(self.rows[row_idx].len() as u16);
+        }
+
+        let mut content = Self::rows_to_file(self.rows.clone(), format);
+
+        // appended text starts on a line of its own, and without a second byte order mark
+        let existing = if append && Path::new(filepath).is_file() {
+            decode(read(filepath)?).0
         } else {
-            true
+            String::new()
+        };
+        if !existing.is_empty() && !existing.ends_with(['\n', '\r']) {
+            content.insert_str(0, format.line_ending);
+        }
+
+        let mut content = encode(content.as_str(), format.encoding.as_str())?;
+        if !existing.is_empty() {
+            let bom = match format.encoding.as_str() {
+                "utf-8-bom" => 3,
+                "utf-16le" | "utf-16be" => 2,
+                _ => 0,
+            };
+            content.drain(..bom);
         }
-    }
 
-    pub fn save(&mut self) -> Result<()> {
         // Open the file with write mode and create it if it doesn't exist
         let mut file = OpenOptions::new()
             .create(true)
             .write(true)
-            .truncate(true)
-            .open(&self.filepath)?;
+            .append(append)
+            .truncate(!append)
+            .open(filepath)?;
 
-        file.write_all(Self::rows_to_file(self.rows.clone()).as_bytes())?;
+        file.write_all(&content)?;
 
         Ok(())
     }
 
-    pub fn set_filepath(&mut self, filepath: String) -> Result<()> {
+    // points the buffer at another file, keeping what is in it and its local options, such as
+    // the encoding it was read as, which the new place's .editorconfig only adds to
+    pub fn rename(&mut self, filepath: String) {
+        let local = std::mem::take(&mut self.options);
+        self.point_at(filepath);
+        self.options.extend(&local);
+    }
+
+    // the tab's file and what comes with its place: options from .editorconfig and the project
+    fn point_at(&mut self, filepath: String) {
         let file_name: String = match Path::new(filepath.as_str()).file_name() {
             Some(filename) => filename.to_string_lossy().to_string(),
             None => String::from("New Tab"),
@@ -107,11 +402,33 @@ impl Editor {
 
         self.title = file_name;
         self.filepath = filepath;
-        self.rows = Self::file_to_rows(self.filepath.clone())?;
+        self.kind = BufferKind::File;
+        (self.options, self.warnings) = editorconfig::options_for(self.filepath.as_str());
+        self.project_config = Config::project_config(Path::new(&self.filepath));
+    }
+
+    pub fn set_filepath(&mut self, filepath: String) -> Result<()> {
+        self.point_at(filepath);
+        let encoding;
+        (self.rows, encoding) = Self::file_to_rows(self.filepath.clone())?;
+        keep_encoding(&mut self.options, encoding);
+        self.marks.clear();
+        self.jumps.clear();
+        self.jump_index = 0;
+        self.extra_cursors.clear();
 
-        if self.rows.len() == 0 {
+        if self.rows.is_empty() {
             self.rows.push(String::new());
         }
+        self.saved = rows_hash(&self.rows);
+
+        // the cursor stays where it was as far as the new text allows, as `:e` reloading wants
+        self.goto_row((self.cursor.y + self.rowoff) as usize);
+
+        let row = &self.rows[(self.cursor.y + self.rowoff) as usize];
+        while !row.is_char_boundary(self.cursor.x as usize) {
+            self.cursor.x -= 1;
+        }
 
         Ok(())
     }
@@ -165,7 +482,7 @@ impl Editor {
         self.cursor.x = pos_x;
 
         if curr_row + 1 < self.rows.len() as u16 {
-            if self.cursor.y <= self.editor_size.y - 1 {
+            if self.cursor.y < self.editor_size.y {
                 self.cursor.y += 1;
             } else {
                 self.rowoff += 1;
@@ -200,71 +517,1497 @@ impl Editor {
         }
     }
 
+    // puts the cursor on row `row`, scrolling only as far as needed to show it
+    pub fn goto_row(&mut self, row: usize) {
+        let row = row.min(self.rows.len() - 1) as u16;
+
+        if row < self.rowoff {
+            self.rowoff = row;
+        } else if row > self.rowoff + self.editor_size.y {
+            self.rowoff = row - self.editor_size.y;
+        }
+
+        self.cursor.y = row - self.rowoff;
+        self.cursor.x = self.cursor.x.min(self.rows[row as usize].len() as u16);
+    }
+
+    // rows shown at once, `editor_size.y` is the last screen row
+    fn view_height(&self) -> usize {
+        self.editor_size.y as usize + 1
+    }
+
+    // cursor on `row` with the view starting at `top`, or as close to it as keeps `row` shown
+    fn show_row(&mut self, row: usize, top: usize) {
+        let row = row.min(self.rows.len() - 1);
+        let top = top
+            .min(row)
+            .max((row + 1).saturating_sub(self.view_height()));
+
+        self.rowoff = top as u16;
+        self.cursor.y = (row - top) as u16;
+        self.cursor_on_first_non_blank();
+    }
+
+    // `scrolloff` can't be more than half the view
+    fn margin(&self, scrolloff: usize) -> usize {
+        scrolloff.min((self.view_height() - 1) / 2)
+    }
+
+    // scrolls so `scrolloff` rows stay shown above and below the cursor where there are any
+    pub fn keep_margin(&mut self, scrolloff: usize) {
+        let row = (self.cursor.y + self.rowoff) as usize;
+        let margin = self.margin(scrolloff);
+        let last = self.rows.len() - 1;
+
+        let lowest = ((row + margin).min(last) + 1).saturating_sub(self.view_height());
+        let top = (self.rowoff as usize).clamp(lowest, row.saturating_sub(margin).max(lowest));
+
+        self.rowoff = top as u16;
+        self.cursor.y = (row - top) as u16;
+    }
+
+    // `Ctrl-f` and `Ctrl-b`, a page less two rows of overlap, the cursor at the new top or bottom
+    pub fn scroll_page(&mut self, forward: bool, count: usize, scrolloff: usize) {
+        let height = self.view_height();
+        let amount = height.saturating_sub(2).max(1) * count;
+        let margin = self.margin(scrolloff);
+        let top = self.rowoff as usize;
+
+        if forward {
+            let top = (top + amount).min(self.rows.len() - 1);
+            self.show_row(top + margin, top);
+        } else {
+            let top = top.saturating_sub(amount);
+            self.show_row(top + height - 1 - margin, top);
+        }
+    }
+
+    // `Ctrl-d` and `Ctrl-u`, the view and the cursor move by `amount` rows, half the view by default
+    pub fn scroll_half(&mut self, forward: bool, amount: Option<usize>) {
+        let amount = amount.unwrap_or(self.view_height() / 2).max(1);
+        let row = (self.cursor.y + self.rowoff) as usize;
+        let top = self.rowoff as usize;
+        let last = self.rows.len() - 1;
+
+        if forward {
+            let top = (top + amount).min(last.saturating_sub(self.view_height() - 1));
+            self.show_row(row + amount, top.max(self.rowoff as usize));
+        } else {
+            self.show_row(row.saturating_sub(amount), top.saturating_sub(amount));
+        }
+    }
+
+    // `H`, `M` and `L`, the top, middle or bottom row shown, `count` rows in from the top or bottom
+    pub fn goto_screen_row(&mut self, place: ScreenRow, count: usize, scrolloff: usize) {
+        let top = self.rowoff as usize;
+        let bottom = (top + self.view_height() - 1).min(self.rows.len() - 1);
+        let offset = count.max(1) - 1;
+        let margin = self.margin(scrolloff);
+
+        // the margin only applies where the view could scroll further
+        let highest = if top > 0 { top + margin } else { top };
+        let lowest = if bottom < self.rows.len() - 1 {
+            bottom - margin
+        } else {
+            bottom
+        };
+
+        let row = match place {
+            ScreenRow::Top => (top + offset).max(highest).min(bottom),
+            ScreenRow::Middle => top + (bottom - top) / 2,
+            ScreenRow::Bottom => bottom.saturating_sub(offset).min(lowest).max(top),
+        };
+
+        self.show_row(row, top);
+    }
+
+    // `zt`, `zz` and `zb`, scrolls the cursor row to the top, middle or bottom of the view
+    pub fn align_view(&mut self, place: ScreenRow, scrolloff: usize) {
+        let row = (self.cursor.y + self.rowoff) as usize;
+        let height = self.view_height();
+        let margin = self.margin(scrolloff);
+
+        let top = match place {
+            ScreenRow::Top => row.saturating_sub(margin),
+            ScreenRow::Middle => row.saturating_sub((height - 1) / 2),
+            ScreenRow::Bottom => (row + margin + 1).saturating_sub(height),
+        };
+
+        self.rowoff = top as u16;
+        self.cursor.y = (row - top) as u16;
+    }
+
+    // `0`/`<Home>` and `$`/`<End>`, the first or last character of the line
+    pub fn goto_line_edge(&mut self, end: bool) {
+        let row = &self.rows[(self.cursor.y + self.rowoff) as usize];
+
+        if end {
+            self.cursor.x = row.len() as u16;
+        } else {
+            self.cursor_on(0);
+        }
+    }
+
+    // `rows` in place of rows `start..end`, always leaving at least one row
+    pub fn replace_rows(&mut self, start: usize, end: usize, rows: Vec<String>) {
+        let end = end.min(self.rows.len());
+        let start = start.min(end);
+        let kept = self.rows.len() - (end - start);
+
+        let removed = self.splice_rows(start, end, rows);
+        self.undo = Some(Undo {
+            start,
+            rows: removed,
+            added: self.rows.len() - kept,
+            after: rows_hash(&self.rows),
+        });
+    }
+
+    // `u`, false when there's nothing to undo
+    pub fn undo(&mut self) -> bool {
+        match self.undo.take() {
+            Some(undo) if rows_hash(&self.rows) == undo.after => {
+                self.splice_rows(undo.start, undo.start + undo.added, undo.rows);
+                self.goto_row(undo.start);
+                self.cursor_on_first_non_blank();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn splice_rows(&mut self, start: usize, end: usize, rows: Vec<String>) -> Vec<String> {
+        self.lines_changed(start, end - start, rows.len());
+        let removed = self.rows.splice(start..end, rows).collect();
+
+        if self.rows.is_empty() {
+            self.rows.push(String::new());
+        }
+
+        removed
+    }
+
     pub fn insert_row(&mut self, idx: usize, row_content: String) {
         if idx > self.rows.len() {
             return;
         }
+        self.lines_changed(idx, 0, 1);
         self.rows.insert(idx, row_content);
     }
 
-    pub fn goto_newline(&mut self) -> Result<()> {
+    // rows `at..at + removed` are being replaced by `added` rows, marks and jumps below move along
+    fn lines_changed(&mut self, at: usize, removed: usize, added: usize) {
+        self.marks = self
+            .marks
+            .iter()
+            .filter_map(|(name, mark)| {
+                marks::moved(*mark, at, removed, added).map(|mark| (*name, mark))
+            })
+            .collect();
+
+        // a jump on a row that is gone goes to where the rows were
+        for jump in self.jumps.iter_mut() {
+            *jump = marks::moved(*jump, at, removed, added).unwrap_or(Mark { row: at, col: 0 });
+        }
+
+        self.extra_cursors = self
+            .extra_cursors
+            .iter()
+            .filter_map(|cursor| marks::moved(*cursor, at, removed, added))
+            .collect();
+    }
+
+    // `gb`, a cursor stays on the word under the cursor and the cursor goes to its next
+    // whole-word match, wrapping around the end
+    pub fn add_cursor_at_next_match(&mut self) -> bool {
+        let (text, offset) = self.text_with_cursor();
+        let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+
+        let word = match text_object::find(&text, offset, 'w', false) {
+            Some(word) if text[word.range.clone()].chars().all(is_word) => word.range,
+            _ => return false,
+        };
+        let needle = &text[word.clone()];
+
+        let matches: Vec<usize> = text
+            .match_indices(needle)
+            .map(|(i, _)| i)
+            .filter(|i| {
+                let before = text[..*i].chars().next_back();
+                let after = text[i + needle.len()..].chars().next();
+                !before.is_some_and(is_word) && !after.is_some_and(is_word)
+            })
+            .collect();
+
+        let here = Mark {
+            row: (self.cursor.y + self.rowoff) as usize,
+            col: self.cursor.x as usize,
+        };
+        let next = matches
+            .iter()
+            .filter(|i| **i > word.start)
+            .chain(matches.iter().filter(|i| **i < word.start))
+            .find_map(|i| {
+                let (row, col) = self.position_of(*i);
+                let taken = self.extra_cursors.iter().any(|cursor| {
+                    cursor.row == row && self.cursor_char_start(row, cursor.col) == col
+                });
+                (!taken).then_some((row, col))
+            });
+
+        match next {
+            Some((row, col)) => {
+                self.extra_cursors.push(here);
+                self.goto_row(row);
+                self.cursor_on(col);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // row and byte column of the bracket pairing with the one under the cursor, in insert mode
+    // the one after the cursor or else the one before it
+    pub fn matching_bracket(&self, insert: bool) -> Option<(usize, usize)> {
         let row_idx = (self.cursor.y + self.rowoff) as usize;
 
-        if self.cursor.x == 0 {
-            self.insert_row(row_idx, String::from(""));
+        let col = if insert {
+            let row = &self.rows[row_idx];
+            let brackets = self.row_brackets(row_idx);
+            let x = (self.cursor.x as usize).min(row.len());
+            let before = row[..x].chars().next_back().map(|ch| x - ch.len_utf8());
+
+            [Some(x), before]
+                .into_iter()
+                .flatten()
+                .find(|i| brackets.iter().any(|(j, _)| j == i))?
         } else {
-            let content = self.rows[row_idx].split_off(self.cursor.x as usize);
-            self.insert_row(row_idx + 1, content);
+            self.char_under_cursor().map_or(0, |range| range.start)
         };
 
-        self.cursor.x = 0;
+        self.bracket_match(row_idx, col)
+    }
 
-        if self.cursor.y <= self.editor_size.y - 1 {
-            self.cursor.y += 1;
+    // `%`, to the bracket pairing with the one under the cursor or with the first one after it
+    // on the line
+    pub fn goto_matching_bracket(&mut self) -> bool {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let start = self.char_under_cursor().map_or(0, |range| range.start);
+
+        let target = self
+            .row_brackets(row_idx)
+            .iter()
+            .find(|(i, _)| *i >= start)
+            .and_then(|(i, _)| self.bracket_match(row_idx, *i));
+
+        match target {
+            Some((row, col)) => {
+                self.push_jump();
+                self.goto_row(row);
+                self.cursor_on(col);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // the bracket scanner state each row starts in, scanned again only once the rows or the
+    // filetype changed
+    fn row_states(&self) -> Ref<'_, Vec<brackets::State>> {
+        let mut hasher = DefaultHasher::new();
+        self.rows.hash(&mut hasher);
+        self.filetype().hash(&mut hasher);
+        let key = Some(hasher.finish());
+
+        if self.row_states.borrow().0 != key {
+            let mut state = brackets::State::Code;
+            let states = self
+                .rows
+                .iter()
+                .map(|row| {
+                    let start = state;
+                    state = brackets::row_brackets(row, self.filetype(), state).1;
+                    start
+                })
+                .collect();
+            *self.row_states.borrow_mut() = (key, states);
+        }
+
+        Ref::map(self.row_states.borrow(), |(_, states)| states)
+    }
+
+    // the brackets of `row` outside strings and comments
+    fn row_brackets(&self, row: usize) -> Vec<(usize, char)> {
+        let state = self.row_states()[row];
+        brackets::row_brackets(&self.rows[row], self.filetype(), state).0
+    }
+
+    // the bracket pairing with the one at byte `col` of `row`, looked for up to `MATCH_ROWS`
+    // away
+    fn bracket_match(&self, row: usize, col: usize) -> Option<(usize, usize)> {
+        let states = self.row_states();
+        let in_row = |row: usize| {
+            brackets::row_brackets(&self.rows[row], self.filetype(), states[row])
+                .0
+                .into_iter()
+                .map(move |(col, ch)| ((row, col), ch))
+        };
+
+        let ch = in_row(row).find(|((_, i), _)| *i == col)?.1;
+        let (to, forward) = brackets::partner(ch)?;
+
+        if forward {
+            let rows = row..(row + MATCH_ROWS + 1).min(self.rows.len());
+            let after = rows
+                .flat_map(in_row)
+                .skip_while(|((r, c), _)| *r == row && *c <= col);
+            brackets::depth_match(after, ch, to)
         } else {
-            self.rowoff += 1;
+            let rows = row.saturating_sub(MATCH_ROWS)..row + 1;
+            let before = rows
+                .rev()
+                .flat_map(|row| in_row(row).rev())
+                .skip_while(|((r, c), _)| *r == row && *c >= col);
+            brackets::depth_match(before, ch, to)
         }
+    }
 
-        Ok(())
+    // byte where the normal-mode block sits for a cursor at `x` on `row`
+    fn cursor_char_start(&self, row: usize, x: usize) -> usize {
+        let row = &self.rows[row];
+        let x = char_boundary(row, x);
+
+        match x {
+            0 => 0,
+            x => x - row[..x].chars().next_back().map_or(0, char::len_utf8),
+        }
     }
 
-    // handling deletion of character
-    pub fn delete(&mut self) {
-        let curr_row = (self.cursor.y + self.rowoff) as usize;
+    // `Ctrl-Down`/`Ctrl-Up`, a cursor stays on this line and the cursor goes to the next or
+    // previous one, for editing the same column of several lines
+    pub fn add_cursor_vertical(&mut self, down: bool, count: usize) {
+        for _ in 0..count {
+            let row = (self.cursor.y + self.rowoff) as usize;
+            let target = if down { row + 1 } else { row.wrapping_sub(1) };
 
-        if curr_row > self.rows.len() {
-            return;
+            if target >= self.rows.len() {
+                break;
+            }
+
+            self.extra_cursors.push(Mark {
+                row,
+                col: self.cursor.x as usize,
+            });
+            self.goto_row(target);
+
+            let row = &self.rows[target];
+            let mut x = (self.cursor.x as usize).min(row.len());
+            while !row.is_char_boundary(x) {
+                x -= 1;
+            }
+            self.cursor.x = x as u16;
         }
-        if self.cursor.x == 0 && self.cursor.y == 0 {
+    }
+
+    // `Ctrl-v`, a block from the cursor or no block when there is one
+    pub fn toggle_block(&mut self) {
+        self.block_start = match self.block_start {
+            Some(_) => None,
+            None => Some(Mark {
+                row: (self.cursor.y + self.rowoff) as usize,
+                col: self.cursor.x as usize,
+            }),
+        };
+    }
+
+    // rows and screen columns the visual block spans, from the cells of its corner characters
+    fn block(&self, tabstop: usize) -> Option<(Range<usize>, Range<usize>)> {
+        let start = self.block_start?;
+        let row = (self.cursor.y + self.rowoff) as usize;
+        let (start_left, start_right) = self.char_cells(start.row, start.col, tabstop);
+        let (left, right) = self.char_cells(row, self.cursor.x as usize, tabstop);
+
+        Some((
+            start.row.min(row)..start.row.max(row) + 1,
+            start_left.min(left)..start_right.max(right),
+        ))
+    }
+
+    // first and past-the-last screen columns of the character a normal-mode cursor at `x` is on
+    fn char_cells(&self, row: usize, x: usize, tabstop: usize) -> (usize, usize) {
+        let start = self.cursor_char_start(row, x);
+        let row = &self.rows[row];
+        let end = row[start..]
+            .chars()
+            .next()
+            .map_or(start, |ch| start + ch.len_utf8());
+
+        (
+            display_width(&row[..start], tabstop),
+            display_width(&row[..end], tabstop).max(1),
+        )
+    }
+
+    // the cells of the visual block with their row, lines too short to reach it have none
+    pub fn block_cells(&self, tabstop: usize) -> Vec<(usize, usize)> {
+        let (rows, cells) = match self.block(tabstop) {
+            Some(block) => block,
+            None => return vec![],
+        };
+
+        rows.flat_map(|row| {
+            let width = display_width(&self.rows[row], tabstop);
+            cells
+                .clone()
+                .filter(move |cell| *cell < width)
+                .map(move |cell| (row, cell))
+        })
+        .collect()
+    }
+
+    // `I` and `A` on a visual block, a cursor before or after the block on each line that
+    // reaches it, the topmost being the main one; false without a block
+    pub fn block_to_cursors(&mut self, append: bool, tabstop: usize) -> bool {
+        let (rows, cells) = match self.block(tabstop) {
+            Some(block) => block,
+            None => return false,
+        };
+
+        let mut cursors = rows.filter_map(|row| {
+            let text = &self.rows[row];
+            let left = byte_at_cell(text, cells.start, tabstop)?;
+            let col = match append {
+                true => byte_at_cell(text, cells.end, tabstop).unwrap_or(text.len()),
+                false => left,
+            };
+
+            Some(Mark { row, col })
+        });
+
+        let main = cursors.next();
+        self.extra_cursors = cursors.collect();
+        self.block_start = None;
+
+        if let Some(main) = main {
+            self.goto_row(main.row);
+            self.cursor.x = main.col as u16;
+        }
+
+        true
+    }
+
+    // `i` leaves the extra cursors before the character they were on, as it does the cursor
+    pub fn extra_cursors_before_char(&mut self) {
+        for i in 0..self.extra_cursors.len() {
+            let cursor = self.extra_cursors[i];
+            self.extra_cursors[i].col = self.cursor_char_start(cursor.row, cursor.col);
+        }
+    }
+
+    // runs `edit` at the cursor and at each extra one in buffer order, moving the cursors
+    // after each edit along with the text they were on
+    pub fn at_cursors(&mut self, mut edit: impl FnMut(&mut Editor)) {
+        if self.extra_cursors.is_empty() {
+            edit(self);
             return;
         }
 
-        if self.cursor.x > 0 {
-            if self.del_char(self.cursor.x as usize - 1) {
-                if self.cursor.x > self.rows[curr_row].len() as u16 {
-                    self.cursor.x = self.rows[curr_row].len() as u16;
+        let main = Mark {
+            row: (self.cursor.y + self.rowoff) as usize,
+            col: self.cursor.x as usize,
+        };
+        let mut cursors: Vec<(Mark, bool)> = self
+            .extra_cursors
+            .iter()
+            .map(|cursor| (*cursor, false))
+            .chain([(main, true)])
+            .collect();
+        cursors.sort_by_key(|(cursor, _)| (cursor.row, cursor.col));
+
+        for i in 0..cursors.len() {
+            let at = cursors[i].0;
+            let rows_before = self.rows.len();
+            let len_before = self.rows[at.row].len();
+
+            self.goto_row(at.row);
+            self.cursor.x = char_boundary(&self.rows[at.row], at.col) as u16;
+            edit(self);
+
+            let now = Mark {
+                row: (self.cursor.y + self.rowoff) as usize,
+                col: self.cursor.x as usize,
+            };
+            let added = self.rows.len() as isize - rows_before as isize;
+
+            for (later, _) in cursors[i + 1..].iter_mut() {
+                // the rest of the line after the edit still ends a line, the last one the edit
+                // left, so a cursor in it keeps its distance from the end
+                if later.row == at.row {
+                    let from_end = len_before - later.col.min(len_before);
+                    later.row = (at.row as isize + added) as usize;
+                    later.col = self.rows[later.row].len().saturating_sub(from_end);
                 } else {
-                    self.cursor.x -= 1;
+                    later.row = (later.row as isize + added) as usize;
                 }
             }
-        } else {
-            let row_content = self.rows[curr_row].clone();
-
-            self.cursor.x = self.rows[curr_row - 1].len() as u16;
-            self.rows[curr_row - 1].push_str(row_content.as_str());
-            self.rows.remove(curr_row);
-            self.cursor.y -= 1;
+            cursors[i].0 = now;
         }
+
+        // cursors that ran into each other become one
+        cursors.dedup_by_key(|(cursor, _)| *cursor);
+
+        let main = cursors
+            .iter()
+            .find(|(_, main)| *main)
+            .map_or(main, |(cursor, _)| *cursor);
+        self.extra_cursors = cursors
+            .iter()
+            .filter(|(cursor, is_main)| !is_main && *cursor != main)
+            .map(|(cursor, _)| *cursor)
+            .collect();
+
+        self.goto_row(main.row);
+        self.cursor.x = main.col as u16;
     }
 
-    fn del_char(&mut self, idx: usize) -> bool {
-        let curr_row = (self.cursor.y + self.rowoff) as usize;
+    // screen column of the cell each extra cursor shows on, with its row; in normal mode the
+    // character before `col`, otherwise the one after it
+    pub fn extra_cursor_cells(&self, normal: bool, tabstop: usize) -> Vec<(usize, usize)> {
+        self.extra_cursors
+            .iter()
+            .map(|cursor| {
+                let row = &self.rows[cursor.row];
+                let col = if normal {
+                    self.cursor_char_start(cursor.row, cursor.col)
+                } else {
+                    char_boundary(row, cursor.col)
+                };
 
-        if idx >= self.rows[curr_row].len() {
-            false
+                (cursor.row, display_width(&row[..col], tabstop))
+            })
+            .collect()
+    }
+
+    // insert mode typing `ch` where it is one of `pairs`: over the same closer after the cursor,
+    // or an opener along with its closer when nothing but a blank or a closer follows; false
+    // when it is typed as usual
+    pub fn type_pair(&mut self, ch: char, pairs: &[(char, char)]) -> bool {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let row = &self.rows[row_idx];
+        let x = (self.cursor.x as usize).min(row.len());
+        let (before, after) = (row[..x].chars().next_back(), row[x..].chars().next());
+
+        let is_closer = pairs.iter().any(|(_, close)| *close == ch);
+        if is_closer && after == Some(ch) {
+            self.cursor.x = (x + ch.len_utf8()) as u16;
+            return true;
+        }
+
+        let close = match pairs.iter().find(|(open, _)| *open == ch) {
+            Some((_, close)) => *close,
+            None => return false,
+        };
+        let closes_here = after
+            .is_none_or(|after| after.is_whitespace() || pairs.iter().any(|(_, c)| *c == after));
+        // a quote after a word is an apostrophe or closes a string
+        let after_word = ch == close && before.is_some_and(|before| before.is_alphanumeric());
+
+        if !closes_here || after_word {
+            return false;
+        }
+
+        self.rows[row_idx].insert(x, ch);
+        self.rows[row_idx].insert(x + ch.len_utf8(), close);
+        self.cursor.x = (x + ch.len_utf8()) as u16;
+        true
+    }
+
+    // Backspace between an opener of `pairs` and its closer deletes both
+    pub fn delete_pair(&mut self, pairs: &[(char, char)]) -> bool {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let row = &self.rows[row_idx];
+        let x = (self.cursor.x as usize).min(row.len());
+
+        let pair = match (row[..x].chars().next_back(), row[x..].chars().next()) {
+            (Some(open), Some(close)) => pairs.contains(&(open, close)).then_some((open, close)),
+            _ => None,
+        };
+
+        match pair {
+            Some((open, close)) => {
+                let start = x - open.len_utf8();
+
+                self.rows[row_idx].replace_range(start..x + close.len_utf8(), "");
+                self.cursor.x = start as u16;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // insert mode typing `ch` at the cursor
+    pub fn type_char(&mut self, ch: char, indent: &Indent) {
+        self.dedent_for_closer(ch, indent);
+
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let x = (self.cursor.x as usize).min(self.rows[row_idx].len());
+
+        self.rows[row_idx].insert(x, ch);
+        self.cursor.x = (x + ch.len_utf8()) as u16;
+    }
+
+    pub fn mark_here(&self) -> Mark {
+        Mark {
+            row: (self.cursor.y + self.rowoff) as usize,
+            col: self.char_under_cursor().map_or(0, |range| range.start),
+        }
+    }
+
+    // `` `a `` goes to the mark's column, `'a` to the first non-blank of its line
+    pub fn goto_mark(&mut self, mark: Mark, linewise: bool) {
+        self.goto_row(mark.row);
+
+        if linewise {
+            self.cursor_on_first_non_blank();
+        } else {
+            let row = &self.rows[(self.cursor.y + self.rowoff) as usize];
+            let mut col = mark.col.min(row.len());
+            while !row.is_char_boundary(col) {
+                col -= 1;
+            }
+            self.cursor_on(col);
+        }
+    }
+
+    // before a big jump, the cursor position goes at the end of the jump list and in the `'` mark
+    pub fn push_jump(&mut self) {
+        let here = self.mark_here();
+
+        self.jumps.retain(|jump| jump.row != here.row);
+        self.jumps.push(here);
+        self.jump_index = self.jumps.len();
+        self.marks.insert('\'', here);
+    }
+
+    // `Ctrl-o`, back `count` entries in the jump list, remembering where it started
+    pub fn jump_back(&mut self, count: usize) {
+        if self.jump_index >= self.jumps.len() {
+            let here = self.mark_here();
+
+            self.jumps.retain(|jump| jump.row != here.row);
+            self.jumps.push(here);
+            self.jump_index = self.jumps.len() - 1;
+        }
+
+        if let Some(index) = self.jump_index.checked_sub(count) {
+            self.jump_index = index;
+            self.goto_mark(self.jumps[index], false);
+        }
+    }
+
+    // `Ctrl-i`, forward again after `Ctrl-o`
+    pub fn jump_forward(&mut self, count: usize) {
+        let index = self.jump_index + count;
+
+        if index < self.jumps.len() {
+            self.jump_index = index;
+            self.goto_mark(self.jumps[index], false);
+        }
+    }
+
+    pub fn filetype(&self) -> &str {
+        let extension = Path::new(self.filepath.as_str())
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+
+        match extension {
+            "rs" => "rust",
+            "py" => "python",
+            "js" | "mjs" | "ts" | "jsx" | "tsx" => "javascript",
+            "c" | "h" | "cpp" | "hpp" | "cc" => "c",
+            "go" => "go",
+            "java" => "java",
+            "json" => "json",
+            "toml" => "toml",
+            "yaml" | "yml" => "yaml",
+            "html" | "xml" => "html",
+            "md" => "markdown",
+            _ => "text",
+        }
+    }
+
+    // characters that indent the following line when they end a line
+    fn indent_openers(&self) -> &'static [char] {
+        match self.filetype() {
+            "text" | "markdown" => &[],
+            "python" | "yaml" => &['{', '(', '[', ':'],
+            _ => &['{', '(', '['],
+        }
+    }
+
+    // indentation for a line following `row_idx` whose text up to the cursor is `before`
+    fn next_indent(&self, row_idx: usize, before: &str, indent: &Indent) -> String {
+        if !indent.auto {
+            return String::new();
+        }
+
+        let width = display_width(leading_whitespace(&self.rows[row_idx]), indent.tabstop);
+        let opens = indent.smart && before.trim_end().ends_with(self.indent_openers());
+
+        indent.whitespace(width + if opens { indent.shiftwidth } else { 0 })
+    }
+
+    fn cursor_down(&mut self) {
+        if self.cursor.y < self.editor_size.y {
+            self.cursor.y += 1;
+        } else {
+            self.rowoff += 1;
+        }
+    }
+
+    pub fn goto_newline(&mut self, indent: &Indent) -> Result<()> {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+
+        if self.cursor.x == 0 {
+            self.insert_row(row_idx, String::from(""));
+            self.cursor.x = 0;
+        } else {
+            let mut content = self.rows[row_idx].split_off(self.cursor.x as usize);
+            let new_indent = self.next_indent(row_idx, &self.rows[row_idx], indent);
+
+            if indent.auto {
+                content = content.trim_start().to_string();
+            }
+
+            // `{|}` splits into three lines with the cursor on the indented middle one
+            if indent.smart
+                && new_indent.len() > leading_whitespace(&self.rows[row_idx]).len()
+                && content.starts_with(INDENT_CLOSERS)
+            {
+                let closer_indent = leading_whitespace(&self.rows[row_idx]).to_string();
+                self.insert_row(row_idx + 1, closer_indent + content.as_str());
+                content = String::new();
+            }
+
+            self.cursor.x = new_indent.len() as u16;
+            self.insert_row(row_idx + 1, new_indent + content.as_str());
+        };
+
+        self.cursor_down();
+
+        Ok(())
+    }
+
+    // `o` and `O`, an indented empty line below or above the cursor
+    pub fn open_line(&mut self, below: bool, indent: &Indent) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+
+        if below {
+            let new_indent = self.next_indent(row_idx, &self.rows[row_idx], indent);
+
+            self.cursor.x = new_indent.len() as u16;
+            self.insert_row(row_idx + 1, new_indent);
+            self.cursor_down();
+        } else {
+            let new_indent = if indent.auto {
+                leading_whitespace(&self.rows[row_idx]).to_string()
+            } else {
+                String::new()
+            };
+
+            self.cursor.x = new_indent.len() as u16;
+            self.insert_row(row_idx, new_indent);
+        }
+    }
+
+    // typing a closing bracket on a blank line moves it back one indent level
+    pub fn dedent_for_closer(&mut self, ch: char, indent: &Indent) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let x = self.cursor.x as usize;
+        let before = &self.rows[row_idx][..x];
+
+        if !indent.smart
+            || !INDENT_CLOSERS.contains(&ch)
+            || before.is_empty()
+            || !before.trim().is_empty()
+            || self.indent_openers().is_empty()
+        {
+            return;
+        }
+
+        let width = display_width(before, indent.tabstop).saturating_sub(indent.shiftwidth);
+        let new_indent = indent.whitespace(width);
+
+        self.rows[row_idx].replace_range(..x, new_indent.as_str());
+        self.cursor.x = new_indent.len() as u16;
+    }
+
+    // Tab in insert mode, spaces up to the next soft tab stop or a literal tab
+    pub fn insert_tab(&mut self, expandtab: bool, softtabstop: usize, tabstop: usize) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let x = self.cursor.x as usize;
+
+        let indent = if expandtab {
+            let col = display_width(&self.rows[row_idx][..x], tabstop);
+            " ".repeat(softtabstop.max(1) - col % softtabstop.max(1))
+        } else {
+            String::from("\t")
+        };
+
+        self.rows[row_idx].insert_str(x, indent.as_str());
+        self.cursor.x += indent.len() as u16;
+    }
+
+    // Backspace over spaces removes back to the previous soft tab stop
+    pub fn delete_soft_tab(&mut self, softtabstop: usize, tabstop: usize) -> bool {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let x = self.cursor.x as usize;
+
+        if softtabstop <= 1 || x == 0 || !self.rows[row_idx][..x].ends_with(' ') {
+            return false;
+        }
+
+        let col = display_width(&self.rows[row_idx][..x], tabstop);
+        let target = (col - 1) / softtabstop * softtabstop;
+        let spaces = self.rows[row_idx][..x]
+            .chars()
+            .rev()
+            .take_while(|ch| *ch == ' ')
+            .count()
+            .min(col - target);
+
+        self.rows[row_idx].replace_range(x - spaces..x, "");
+        self.cursor.x -= spaces as u16;
+
+        true
+    }
+
+    // byte range of the character the normal-mode cursor is on, the one before `cursor.x`
+    fn char_under_cursor(&self) -> Option<Range<usize>> {
+        let row = &self.rows[(self.cursor.y + self.rowoff) as usize];
+        let x = (self.cursor.x as usize).min(row.len());
+
+        if !row.is_char_boundary(x) {
+            return None;
+        }
+
+        match x {
+            0 => row.chars().next().map(|ch| 0..ch.len_utf8()),
+            x => row[..x].chars().next_back().map(|ch| x - ch.len_utf8()..x),
+        }
+    }
+
+    // normal-mode cursor on the character at byte `idx`, or on the last one past the end
+    fn cursor_on(&mut self, idx: usize) {
+        let row = &self.rows[(self.cursor.y + self.rowoff) as usize];
+
+        self.cursor.x = match row.get(idx..).and_then(|rest| rest.chars().next()) {
+            Some(ch) => idx + ch.len_utf8(),
+            None => row.len(),
+        } as u16;
+    }
+
+    // normal-mode cursor on the first non-blank character of the cursor line
+    pub fn cursor_on_first_non_blank(&mut self) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let indent = leading_whitespace(&self.rows[row_idx]).len();
+
+        self.cursor_on(indent);
+    }
+
+    pub fn yank_lines(&self, count: usize) -> Vec<String> {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let end = (row_idx + count.max(1)).min(self.rows.len());
+
+        self.rows[row_idx..end].to_vec()
+    }
+
+    // `dd`, the deleted lines are returned
+    pub fn delete_lines(&mut self, count: usize) -> Vec<String> {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let end = (row_idx + count.max(1)).min(self.rows.len());
+        self.lines_changed(row_idx, end - row_idx, 0);
+        let deleted = self.rows.drain(row_idx..end).collect();
+
+        if self.rows.is_empty() {
+            self.rows.push(String::new());
+        }
+
+        self.goto_row(row_idx);
+        self.cursor_on_first_non_blank();
+
+        deleted
+    }
+
+    // `J`, the next lines lose their indent and are joined with a space where one is needed
+    pub fn join_lines(&mut self, count: usize) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+
+        for _ in 1..count.max(2) {
+            if row_idx + 1 >= self.rows.len() {
+                break;
+            }
+
+            self.lines_changed(row_idx + 1, 1, 0);
+            let next = self.rows.remove(row_idx + 1);
+            let next = next.trim_start();
+            let row = &mut self.rows[row_idx];
+            let join_at = row.len();
+
+            if !row.is_empty()
+                && !next.is_empty()
+                && !row.ends_with([' ', '\t'])
+                && !next.starts_with(')')
+            {
+                row.push(' ');
+            }
+            row.push_str(next);
+
+            self.cursor_on(join_at);
+        }
+    }
+
+    // `x`, the deleted text is returned
+    pub fn delete_chars(&mut self, count: usize) -> String {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let start = match self.char_under_cursor() {
+            Some(range) => range.start,
+            None => return String::new(),
+        };
+
+        let row = &mut self.rows[row_idx];
+        let end = row[start..]
+            .char_indices()
+            .nth(count.max(1))
+            .map(|(i, _)| start + i)
+            .unwrap_or(row.len());
+        let deleted = row[start..end].to_string();
+
+        row.replace_range(start..end, "");
+        self.cursor_on(start);
+
+        deleted
+    }
+
+    // `r`, nothing changes when fewer than `count` characters are left on the line
+    pub fn replace_chars(&mut self, ch: char, count: usize) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let start = match self.char_under_cursor() {
+            Some(range) => range.start,
+            None => return,
+        };
+
+        let row = &mut self.rows[row_idx];
+        let count = count.max(1);
+        let end = match row[start..]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .nth(count)
+        {
+            Some(end) => end,
+            None if row[start..].chars().count() == count => row.len(),
+            None => return,
+        };
+
+        row.replace_range(start..end, ch.to_string().repeat(count).as_str());
+        self.cursor.x = (start + count * ch.len_utf8()) as u16;
+    }
+
+    // `~`, the cursor ends up after the changed characters
+    pub fn toggle_case(&mut self, count: usize) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let start = match self.char_under_cursor() {
+            Some(range) => range.start,
+            None => return,
+        };
+
+        let row = &mut self.rows[row_idx];
+        let end = row[start..]
+            .char_indices()
+            .nth(count.max(1))
+            .map(|(i, _)| start + i)
+            .unwrap_or(row.len());
+        let toggled: String = row[start..end]
+            .chars()
+            .flat_map(|ch| {
+                if ch.is_uppercase() {
+                    ch.to_lowercase().collect::<Vec<char>>()
+                } else {
+                    ch.to_uppercase().collect::<Vec<char>>()
+                }
+            })
+            .collect();
+
+        row.replace_range(start..end, toggled.as_str());
+        self.cursor_on(start + toggled.len());
+    }
+
+    // the lines joined with `\n` and the byte offset of the normal-mode cursor in it, for text objects
+    pub fn text_with_cursor(&self) -> (String, usize) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let col = self.char_under_cursor().map_or(0, |range| range.start);
+        let offset: usize = self.rows[..row_idx].iter().map(|row| row.len() + 1).sum();
+
+        (self.rows.join("\n"), offset + col)
+    }
+
+    // row and byte column of an offset into `text_with_cursor`
+    fn position_of(&self, offset: usize) -> (usize, usize) {
+        let mut start = 0;
+
+        for (row_idx, row) in self.rows.iter().enumerate() {
+            if offset <= start + row.len() {
+                return (row_idx, offset - start);
+            }
+            start += row.len() + 1;
+        }
+
+        let last = self.rows.len() - 1;
+        (last, self.rows[last].len())
+    }
+
+    // what `object` covers as a register, taken out of the buffer with `delete`
+    pub fn take_object(&mut self, object: &TextObject, delete: bool) -> Register {
+        let (start_row, start_col) = self.position_of(object.range.start);
+        let (end_row, end_col) = self.position_of(object.range.end);
+
+        let register = if object.linewise {
+            Register::lines(self.rows[start_row..=end_row].to_vec())
+        } else {
+            let (text, _) = self.text_with_cursor();
+            Register {
+                text: text[object.range.clone()]
+                    .split('\n')
+                    .map(String::from)
+                    .collect(),
+                linewise: false,
+            }
+        };
+
+        if delete {
+            if object.linewise {
+                self.lines_changed(start_row, end_row + 1 - start_row, 0);
+                self.rows.drain(start_row..=end_row);
+                if self.rows.is_empty() {
+                    self.rows.push(String::new());
+                }
+            } else {
+                let rest = self.rows[end_row][end_col..].to_string();
+                self.rows[start_row].truncate(start_col);
+                self.rows[start_row].push_str(&rest);
+                self.lines_changed(start_row + 1, end_row - start_row, 0);
+                self.rows.drain(start_row + 1..=end_row);
+            }
+        }
+
+        self.goto_row(start_row);
+        if object.linewise {
+            self.cursor_on_first_non_blank();
+        } else {
+            self.cursor_on(start_col);
+        }
+
+        register
+    }
+
+    // `c` with a text object, the text goes and the cursor is left where typing replaces it
+    pub fn change_object(&mut self, object: &TextObject) -> Register {
+        let (start_row, start_col) = self.position_of(object.range.start);
+        let (end_row, _) = self.position_of(object.range.end);
+
+        if !object.linewise {
+            let register = self.take_object(object, true);
+            self.cursor.x = start_col as u16;
+            return register;
+        }
+
+        // whole lines leave one empty line behind
+        let register = self.take_object(object, false);
+        self.replace_rows(start_row, end_row + 1, vec![String::new()]);
+        self.goto_row(start_row);
+        self.cursor.x = 0;
+
+        register
+    }
+
+    // `cc`, the lines become one keeping the first one's indent with `autoindent`
+    pub fn change_lines(&mut self, count: usize, autoindent: bool) -> Vec<String> {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let end = (row_idx + count.max(1)).min(self.rows.len());
+        let indent = if autoindent {
+            leading_whitespace(&self.rows[row_idx]).to_string()
+        } else {
+            String::new()
+        };
+
+        self.cursor.x = indent.len() as u16;
+        self.lines_changed(row_idx, end - row_idx, 1);
+        self.rows.splice(row_idx..end, [indent]).collect()
+    }
+
+    // `p` after the cursor (below the line for whole lines) and `P` before it
+    pub fn put(&mut self, register: &Register, after: bool) {
+        if register.text.is_empty() {
+            return;
+        }
+
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+
+        if register.linewise {
+            let at = if after { row_idx + 1 } else { row_idx };
+
+            self.lines_changed(at, 0, register.text.len());
+            self.rows.splice(at..at, register.text.iter().cloned());
+            self.goto_row(at);
+            self.cursor_on_first_non_blank();
+        } else {
+            let at = match (self.char_under_cursor(), after) {
+                (Some(range), true) => range.end,
+                (Some(range), false) => range.start,
+                (None, _) => 0,
+            };
+
+            let rest = self.rows[row_idx].split_off(at);
+            let last_idx = row_idx + register.text.len() - 1;
+
+            self.rows[row_idx].push_str(&register.text[0]);
+            self.lines_changed(row_idx + 1, 0, register.text.len() - 1);
+            self.rows
+                .splice(row_idx + 1..row_idx + 1, register.text[1..].iter().cloned());
+
+            let end = self.rows[last_idx].len();
+            self.rows[last_idx].push_str(&rest);
+
+            // on the last character put for one line, at the start of the text for more
+            if register.text.len() == 1 {
+                self.cursor.x = end as u16;
+            } else {
+                self.cursor_on(at);
+            }
+        }
+    }
+
+    // insert-mode `<Left>`/`<Right>`, the cursor goes between characters rather than on one
+    pub fn insert_move(&mut self, right: bool) {
+        let row = &self.rows[(self.cursor.y + self.rowoff) as usize];
+        let x = (self.cursor.x as usize).min(row.len());
+
+        let step = if right {
+            row[x..].chars().next().map_or(0, char::len_utf8)
+        } else {
+            row[..x].chars().next_back().map_or(0, char::len_utf8)
+        };
+
+        self.cursor.x = if right { x + step } else { x - step } as u16;
+    }
+
+    // insert-mode `<Up>`/`<Down>`, kept on a character boundary of the new line
+    pub fn insert_move_vertical(&mut self, down: bool) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let target = if down {
+            row_idx + 1
+        } else {
+            match row_idx.checked_sub(1) {
+                Some(row) => row,
+                None => return,
+            }
+        };
+
+        self.goto_row(target);
+
+        let row = &self.rows[(self.cursor.y + self.rowoff) as usize];
+        let mut x = self.cursor.x as usize;
+        while !row.is_char_boundary(x) {
+            x -= 1;
+        }
+        self.cursor.x = x as u16;
+    }
+
+    // replace mode typing `ch` over the character at the cursor, which is returned
+    pub fn overwrite_char(&mut self, ch: char) -> Option<char> {
+        let row = &mut self.rows[(self.cursor.y + self.rowoff) as usize];
+        let x = (self.cursor.x as usize).min(row.len());
+        let original = row[x..].chars().next();
+        let end = x + original.map_or(0, char::len_utf8);
+
+        row.replace_range(x..end, ch.encode_utf8(&mut [0; 4]));
+        self.cursor.x = (x + ch.len_utf8()) as u16;
+
+        original
+    }
+
+    // Backspace in replace mode, the character before the cursor goes back to `original`
+    // or away if it was added
+    pub fn restore_char(&mut self, original: Option<char>) {
+        let row = &mut self.rows[(self.cursor.y + self.rowoff) as usize];
+        let x = (self.cursor.x as usize).min(row.len());
+        let start = x - row[..x].chars().next_back().map_or(0, char::len_utf8);
+
+        match original {
+            Some(ch) => row.replace_range(start..x, ch.encode_utf8(&mut [0; 4])),
+            None => row.replace_range(start..x, ""),
+        }
+        self.cursor.x = start as u16;
+    }
+
+    // forward `<Del>` in insert mode, at the end of a line the next one is joined to it
+    pub fn delete_forward(&mut self) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let x = (self.cursor.x as usize).min(self.rows[row_idx].len());
+
+        if x < self.rows[row_idx].len() {
+            self.rows[row_idx].remove(x);
+        } else if row_idx + 1 < self.rows.len() {
+            self.lines_changed(row_idx + 1, 1, 0);
+            let next = self.rows.remove(row_idx + 1);
+            self.rows[row_idx].push_str(&next);
+        }
+    }
+
+    // `Ctrl-w`, the blanks before the cursor and the word or punctuation before them,
+    // at the start of a line the line break
+    pub fn delete_word_before(&mut self) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let x = (self.cursor.x as usize).min(self.rows[row_idx].len());
+
+        if x == 0 {
+            self.delete_line_break();
+            return;
+        }
+
+        let before = &self.rows[row_idx][..x];
+        let blanks = before.len() - before.trim_end().len();
+        let word = &before[..x - blanks];
+
+        let start = match word.chars().next_back() {
+            Some(last) => {
+                let same = |ch: char| {
+                    !ch.is_whitespace()
+                        && (ch.is_alphanumeric() || ch == '_')
+                            == (last.is_alphanumeric() || last == '_')
+                };
+                word.trim_end_matches(same).len()
+            }
+            None => 0,
+        };
+
+        self.rows[row_idx].replace_range(start..x, "");
+        self.cursor.x = start as u16;
+    }
+
+    // `Ctrl-u`, everything before the cursor on its line, at the start of a line the line break
+    pub fn delete_to_line_start(&mut self) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let x = (self.cursor.x as usize).min(self.rows[row_idx].len());
+
+        if x == 0 {
+            self.delete_line_break();
+        } else {
+            self.rows[row_idx].replace_range(..x, "");
+            self.cursor.x = 0;
+        }
+    }
+
+    // the cursor line joined to the one above, the cursor where they meet
+    fn delete_line_break(&mut self) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+
+        if row_idx == 0 {
+            return;
+        }
+
+        self.lines_changed(row_idx, 1, 0);
+        let row = self.rows.remove(row_idx);
+        let x = self.rows[row_idx - 1].len();
+
+        self.rows[row_idx - 1].push_str(&row);
+        self.goto_row(row_idx - 1);
+        self.cursor.x = x as u16;
+    }
+
+    // `Ctrl-r` in insert mode, `text` typed at the cursor with `\n` starting new lines
+    pub fn insert_text(&mut self, text: &str) {
+        let row_idx = (self.cursor.y + self.rowoff) as usize;
+        let x = (self.cursor.x as usize).min(self.rows[row_idx].len());
+        let rest = self.rows[row_idx].split_off(x);
+        let mut lines = text.split('\n');
+
+        self.rows[row_idx].push_str(lines.next().unwrap_or(""));
+
+        let new_rows: Vec<String> = lines.map(String::from).collect();
+        let last = row_idx + new_rows.len();
+        self.lines_changed(row_idx + 1, 0, new_rows.len());
+        self.rows.splice(row_idx + 1..row_idx + 1, new_rows);
+
+        self.goto_row(last);
+        self.cursor.x = self.rows[last].len() as u16;
+        self.rows[last].push_str(&rest);
+    }
+
+    // handling deletion of character
+    pub fn delete(&mut self) {
+        let curr_row = (self.cursor.y + self.rowoff) as usize;
+
+        if curr_row > self.rows.len() {
+            return;
+        }
+        if self.cursor.x == 0 && self.cursor.y == 0 {
+            return;
+        }
+
+        if self.cursor.x > 0 {
+            if self.del_char(self.cursor.x as usize - 1) {
+                if self.cursor.x > self.rows[curr_row].len() as u16 {
+                    self.cursor.x = self.rows[curr_row].len() as u16;
+                } else {
+                    self.cursor.x -= 1;
+                }
+            }
+        } else {
+            let row_content = self.rows[curr_row].clone();
+
+            self.cursor.x = self.rows[curr_row - 1].len() as u16;
+            self.rows[curr_row - 1].push_str(row_content.as_str());
+            self.lines_changed(curr_row, 1, 0);
+            self.rows.remove(curr_row);
+            self.cursor.y -= 1;
+        }
+    }
+
+    fn del_char(&mut self, idx: usize) -> bool {
+        let curr_row = (self.cursor.y + self.rowoff) as usize;
+
+        if idx >= self.rows[curr_row].len() {
+            false
         } else {
             self.rows[curr_row].remove(idx);
             true
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(rows: &[&str]) -> Editor {
+        let rows = rows.iter().map(|row| row.to_string()).collect();
+        let mut tab = Editor::scratch("test", rows, BufferKind::File);
+        tab.editor_size.y = 20;
+        tab
+    }
+
+    fn place(tab: &mut Editor, main: (usize, usize), extra: &[(usize, usize)]) {
+        tab.goto_row(main.0);
+        tab.cursor.x = main.1 as u16;
+        tab.extra_cursors = extra.iter().map(|&(row, col)| Mark { row, col }).collect();
+    }
+
+    fn main_cursor(tab: &Editor) -> (usize, usize) {
+        ((tab.cursor.y + tab.rowoff) as usize, tab.cursor.x as usize)
+    }
+
+    fn extra(tab: &Editor) -> Vec<(usize, usize)> {
+        tab.extra_cursors.iter().map(|m| (m.row, m.col)).collect()
+    }
+
+    const PAIRS: &[(char, char)] = &[('(', ')')];
+
+    #[test]
+    fn brackets_in_comments_across_rows_dont_match() {
+        let mut tab = editor(&["fn f() {", "    /* }", "    { */", "}"]);
+        tab.filepath = "test.rs".to_string();
+
+        assert_eq!(tab.bracket_match(0, 7), Some((3, 0)));
+        assert_eq!(tab.bracket_match(3, 0), Some((0, 7)));
+        assert_eq!(tab.bracket_match(1, 7), None);
+    }
+
+    #[test]
+    fn raw_strings_carry_over_rows() {
+        let mut tab = editor(&["f(`", ")`, 1)"]);
+        tab.filepath = "test.go".to_string();
+
+        assert_eq!(tab.bracket_match(0, 1), Some((1, 5)));
+    }
+
+    #[test]
+    fn stale_extra_cursor_lands_on_a_char() {
+        // `gb` on the first `éé` of "a éé éé", then `x` on the `a`
+        let mut tab = editor(&[" éé éé"]);
+        place(&mut tab, (0, 0), &[(0, 10)]);
+        assert_eq!(tab.extra_cursor_cells(true, 8), [(0, 5)]);
+
+        tab.extra_cursors[0].col = 4;
+        assert_eq!(tab.extra_cursor_cells(true, 8), [(0, 1)]);
+        assert_eq!(tab.extra_cursor_cells(false, 8), [(0, 2)]);
+    }
+
+    #[test]
+    fn typing_a_pair_moves_the_cursors_after_it() {
+        let mut tab = editor(&["x x"]);
+        place(&mut tab, (0, 1), &[(0, 3)]);
+        tab.at_cursors(|tab| {
+            tab.type_pair('(', PAIRS);
+        });
+
+        assert_eq!(tab.rows, ["x() x()"]);
+        assert_eq!(main_cursor(&tab), (0, 2));
+        assert_eq!(extra(&tab), [(0, 6)]);
+    }
+
+    #[test]
+    fn deleting_pairs_at_each_cursor() {
+        let mut tab = editor(&["() ()"]);
+        place(&mut tab, (0, 1), &[(0, 4)]);
+        tab.at_cursors(|tab| {
+            tab.delete_pair(PAIRS);
+        });
+
+        assert_eq!(tab.rows, [" "]);
+        assert_eq!(main_cursor(&tab), (0, 0));
+        assert_eq!(extra(&tab), [(0, 1)]);
+    }
+
+    #[test]
+    fn newline_at_each_cursor_shifts_the_rows_below() {
+        let indent = Indent {
+            auto: false,
+            smart: false,
+            shiftwidth: 4,
+            tabstop: 8,
+            expandtab: true,
+        };
+        let mut tab = editor(&["ab", "cd"]);
+        place(&mut tab, (0, 1), &[(1, 1)]);
+        tab.at_cursors(|tab| tab.goto_newline(&indent).unwrap());
+
+        assert_eq!(tab.rows, ["a", "b", "c", "d"]);
+        assert_eq!(main_cursor(&tab), (1, 0));
+        assert_eq!(extra(&tab), [(3, 0)]);
+    }
+
+    #[test]
+    fn backspace_joining_lines_at_each_cursor() {
+        let mut tab = editor(&["a", "b", "c"]);
+        place(&mut tab, (1, 0), &[(2, 0)]);
+        tab.at_cursors(|tab| tab.delete());
+
+        assert_eq!(tab.rows, ["abc"]);
+        assert_eq!(main_cursor(&tab), (0, 1));
+        assert_eq!(extra(&tab), [(0, 2)]);
+    }
+
+    #[test]
+    fn cursors_that_meet_become_one() {
+        let mut tab = editor(&["ab"]);
+        place(&mut tab, (0, 1), &[(0, 2)]);
+        tab.at_cursors(|tab| tab.delete());
+        tab.at_cursors(|tab| tab.delete());
+
+        assert_eq!(tab.rows, [""]);
+        assert_eq!(main_cursor(&tab), (0, 0));
+        assert!(tab.extra_cursors.is_empty());
+    }
+}
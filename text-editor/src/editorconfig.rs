@@ -0,0 +1,314 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use crate::options::{self, OptionValue, Options};
+
+const EDITORCONFIG: &str = ".editorconfig";
+
+struct Section {
+    pattern: String,
+    properties: Vec<(String, String)>,
+}
+
+struct EditorConfig {
+    dir: PathBuf,
+    root: bool,
+    sections: Vec<Section>,
+}
+
+// buffer-local options from the .editorconfig files that apply to `filepath`, with a warning
+// for each one that can't be read and is left out
+pub fn options_for(filepath: &str) -> (Options, Vec<String>) {
+    let filepath = env::current_dir()
+        .map(|dir| dir.join(filepath))
+        .unwrap_or_else(|_| PathBuf::from(filepath));
+    let mut configs = vec![];
+    let mut warnings = vec![];
+
+    for dir in filepath.ancestors().skip(1) {
+        let path = dir.join(EDITORCONFIG);
+
+        if path.is_file() {
+            let content = match read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    warnings.push(format!("{} skipped: {}", path.display(), e));
+                    continue;
+                }
+            };
+            let config = parse(dir, content.as_str());
+            let root = config.root;
+            configs.push(config);
+
+            if root {
+                break;
+            }
+        }
+    }
+
+    // the outermost file first so closer ones override it
+    let mut properties: HashMap<String, String> = HashMap::new();
+
+    for config in configs.iter().rev() {
+        let relative = filepath
+            .strip_prefix(&config.dir)
+            .unwrap_or(&filepath)
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        for section in config.sections.iter() {
+            if section_matches(section.pattern.as_str(), relative.as_str()) {
+                for (key, value) in section.properties.iter() {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    (to_options(&properties), warnings)
+}
+
+fn parse(dir: &Path, content: &str) -> EditorConfig {
+    let mut config = EditorConfig {
+        dir: dir.to_path_buf(),
+        root: false,
+        sections: vec![],
+    };
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(pattern) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            config.sections.push(Section {
+                pattern: pattern.to_string(),
+                properties: vec![],
+            });
+        } else if let Some((key, value)) = line.split_once('=') {
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_lowercase();
+
+            match config.sections.last_mut() {
+                Some(section) => section.properties.push((key, value)),
+                None if key == "root" => config.root = value == "true",
+                None => {}
+            }
+        }
+    }
+
+    config
+}
+
+// patterns with a slash are relative to the .editorconfig, others match the file name anywhere
+fn section_matches(pattern: &str, relative: &str) -> bool {
+    let (pattern, target) = if pattern.contains('/') {
+        (pattern.trim_start_matches('/'), relative)
+    } else {
+        (pattern, relative.rsplit('/').next().unwrap_or(relative))
+    };
+
+    expand_braces(pattern).iter().any(|pattern| {
+        glob_match(
+            &pattern.chars().collect::<Vec<char>>(),
+            &target.chars().collect::<Vec<char>>(),
+        )
+    })
+}
+
+// `*.{js,py}` -> [`*.js`, `*.py`] and `{1..3}` -> [`1`, `2`, `3`]
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let open = match pattern.find('{') {
+        Some(open) => open,
+        None => return vec![pattern.to_string()],
+    };
+
+    let mut depth = 0;
+    let mut close = None;
+    let mut splits = vec![];
+
+    for (i, ch) in pattern[open..].char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(open + i);
+                    break;
+                }
+            }
+            ',' if depth == 1 => splits.push(open + i),
+            _ => {}
+        }
+    }
+
+    let close = match close {
+        Some(close) => close,
+        None => return vec![pattern.to_string()],
+    };
+
+    let inner = &pattern[open + 1..close];
+    let alternatives: Vec<String> = if splits.is_empty() {
+        match inner
+            .split_once("..")
+            .and_then(|(start, end)| Some((start.parse::<i64>().ok()?, end.parse::<i64>().ok()?)))
+        {
+            Some((start, end)) => (start.min(end)..=start.max(end))
+                .map(|n| n.to_string())
+                .collect(),
+            None => vec![format!("{{{}}}", inner)],
+        }
+    } else {
+        let mut parts = vec![];
+        let mut last = open + 1;
+
+        for split in splits {
+            parts.push(pattern[last..split].to_string());
+            last = split + 1;
+        }
+        parts.push(pattern[last..close].to_string());
+
+        parts
+    };
+
+    let rest = expand_braces(&pattern[close + 1..]);
+
+    alternatives
+        .iter()
+        .flat_map(|alternative| {
+            expand_braces(alternative)
+                .into_iter()
+                .flat_map(|alternative| {
+                    rest.iter()
+                        .map(move |rest| format!("{}{}{}", &pattern[..open], alternative, rest))
+                })
+        })
+        .collect()
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            (0..=text.len()).any(|i| glob_match(&pattern[2..], &text[i..]))
+        }
+        Some('*') => (0..=text.len())
+            .take_while(|i| *i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(&pattern[1..], &text[i..])),
+        Some('?') => !text.is_empty() && text[0] != '/' && glob_match(&pattern[1..], &text[1..]),
+        Some('[') => match pattern.iter().skip(1).position(|ch| *ch == ']') {
+            Some(end) if !text.is_empty() => {
+                let class = &pattern[1..end + 1];
+                let (negated, class) = match class.first() {
+                    Some('!') => (true, &class[1..]),
+                    _ => (false, class),
+                };
+
+                let mut matched = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        matched |= class[i] <= text[0] && text[0] <= class[i + 2];
+                        i += 3;
+                    } else {
+                        matched |= class[i] == text[0];
+                        i += 1;
+                    }
+                }
+
+                matched != negated && glob_match(&pattern[end + 2..], &text[1..])
+            }
+            _ => text.first() == Some(&'[') && glob_match(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &text[1..])
+        }
+        Some(ch) => text.first() == Some(ch) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+fn to_options(properties: &HashMap<String, String>) -> Options {
+    let mut values: Vec<(&str, OptionValue)> = vec![];
+    let get = |key: &str| {
+        properties
+            .get(key)
+            .map(|value| value.as_str())
+            .filter(|value| *value != "unset")
+    };
+    let number = |key: &str| get(key).and_then(|value| value.parse::<usize>().ok());
+
+    match get("indent_style") {
+        Some("tab") => values.push(("expandtab", OptionValue::Bool(false))),
+        Some("space") => values.push(("expandtab", OptionValue::Bool(true))),
+        _ => {}
+    }
+
+    // indent_size = tab follows tab_width, and tab_width defaults to indent_size
+    match get("indent_size") {
+        Some("tab") => values.push(("shiftwidth", OptionValue::Number(0))),
+        _ => {
+            if let Some(size) = number("indent_size") {
+                values.push(("shiftwidth", OptionValue::Number(size)));
+                values.push(("softtabstop", OptionValue::Number(size)));
+
+                if number("tab_width").is_none() {
+                    values.push(("tabstop", OptionValue::Number(size)));
+                }
+            }
+        }
+    }
+    if let Some(width) = number("tab_width") {
+        values.push(("tabstop", OptionValue::Number(width)));
+    }
+
+    match get("end_of_line") {
+        Some("lf") => values.push(("fileformat", OptionValue::Text("unix".to_string()))),
+        Some("crlf") => values.push(("fileformat", OptionValue::Text("dos".to_string()))),
+        Some("cr") => values.push(("fileformat", OptionValue::Text("mac".to_string()))),
+        _ => {}
+    }
+
+    if let Some(charset) = get("charset") {
+        if let Some(def) = options::find("fileencoding") {
+            if let Some(value) = OptionValue::parse(def.kind, charset) {
+                values.push(("fileencoding", value));
+            }
+        }
+    }
+
+    for (key, option) in [
+        ("trim_trailing_whitespace", "trimtrailingwhitespace"),
+        ("insert_final_newline", "fixendofline"),
+    ] {
+        match get(key) {
+            Some("true") => values.push((option, OptionValue::Bool(true))),
+            Some("false") => values.push((option, OptionValue::Bool(false))),
+            _ => {}
+        }
+    }
+
+    match get("max_line_length") {
+        Some("off") => values.push(("textwidth", OptionValue::Number(0))),
+        _ => {
+            if let Some(length) = number("max_line_length") {
+                values.push(("textwidth", OptionValue::Number(length)));
+            }
+        }
+    }
+
+    let mut options = Options::default();
+
+    for (name, value) in values {
+        if let Some(def) = options::find(name) {
+            options.set(def, value);
+        }
+    }
+
+    options
+}
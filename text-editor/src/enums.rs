@@ -1,8 +1,10 @@
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Mode {
     Normal,
     Insert,
     Command,
+    // `R`, typed characters take the place of the ones under the cursor
+    Replace,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -19,14 +21,18 @@ pub enum LineNumber {
     Absolute,
 }
 
-#[derive(Debug)]
-pub enum Element {
-    Num(usize),
-    Char(char),
+// where `H`/`M`/`L` go and `zt`/`zz`/`zb` put the cursor row
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScreenRow {
+    Top,
+    Middle,
+    Bottom,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum Action {
-    Delete,
-    Default,
+// what a tab shows, only `File` buffers are read from and written to disk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BufferKind {
+    File,
+    // the `q:` window, Enter runs the line under the cursor
+    CommandHistory,
 }
@@ -1,77 +1,118 @@
-use std::path::Path;
+use std::{
+    fs::{metadata, set_permissions},
+    io,
+    path::Path,
+    process::Output,
+};
 
-use crate::{editor::Editor, kass::Kass};
+use crate::{
+    editor::Editor,
+    enums::Mode,
+    kass::Kass,
+    keymap, marks,
+    options::{self, OptionKind, OptionValue, SetArg},
+    prompt::{Prompt, PromptAction},
+    range::LineRange,
+    shell,
+};
 
+// `line_number` counts from 1, past the end goes to the last line
 pub fn goto_line(kass: &mut Kass, line_number: usize) {
-    let current_pos = kass.app.tabs[kass.app.active_index].rowoff + kass.cursor.y;
-
-    if line_number == kass.app.tabs[kass.app.active_index].rows.len() {
-        let last_line = kass.app.tabs[kass.app.active_index].rows.len() - 1;
-
-        kass.app.tabs[kass.app.active_index].cursor.y =
-            kass.app.tabs[kass.app.active_index].editor_size.y - 1;
-
-        kass.app.tabs[kass.app.active_index].rowoff =
-            last_line as u16 - kass.app.tabs[kass.app.active_index].editor_size.y + 1;
-    } else if current_pos >= line_number as u16 - 1 {
-        for _ in 0..current_pos - line_number as u16 + 1 {
-            kass.app.tabs[kass.app.active_index].move_up(1);
-        }
-    } else if current_pos < line_number as u16 - 1 {
-        for _ in 0..line_number as u16 - 1 - current_pos {
-            kass.app.tabs[kass.app.active_index].move_down(1);
-        }
-    }
+    kass.app.tabs[kass.app.active_index].push_jump();
+    kass.app.tabs[kass.app.active_index].goto_row(line_number.saturating_sub(1));
 }
 
+// asks before dropping changes to the active tab
+// `:e PATH`, or `:e` alone to load the tab's file again
 pub fn edit_file(input: &str, _close: &mut bool, kass: &mut Kass) {
-    if !Path::new(input).is_dir() {
-        match kass.app.tabs[kass.app.active_index].set_filepath(input.to_string()) {
-            Ok(_) => {}
-            Err(e) => kass.set_error(e.to_string().as_str()),
-        }
-    } else {
+    let input = match input.trim() {
+        "" => kass.app.tabs[kass.app.active_index].filepath.clone(),
+        input => input.to_string(),
+    };
+    let input = input.as_str();
+
+    if input.is_empty() {
+        kass.set_error("No file name")
+    } else if Path::new(input).is_dir() {
         kass.set_error("Cannot edit a directory. Provide a file path")
+    } else if !kass.app.tabs[kass.app.active_index].is_saved() {
+        let message = format!(
+            "Save changes to {}?",
+            kass.app.tabs[kass.app.active_index].title
+        );
+        kass.app.prompt = Some(Prompt::new(
+            message.as_str(),
+            PromptAction::Edit(input.to_string()),
+        ));
+    } else {
+        open_file(kass, input);
     }
 }
 
-pub fn quit(input: &str, close: &mut bool, kass: &mut Kass) {
-    let mut to_remove = kass.app.active_index;
+pub fn open_file(kass: &mut Kass, filepath: &str) {
+    kass.keep_file_marks(kass.app.active_index);
 
-    if let Ok(number) = i32::from_str_radix(input, 10) {
-        to_remove = number as usize;
+    match kass.app.tabs[kass.app.active_index].set_filepath(filepath.to_string()) {
+        Ok(_) => {}
+        Err(e) => kass.set_error(e.to_string().as_str()),
     }
+}
 
-    if kass.app.tabs[to_remove].is_saved() {
-        if to_remove < kass.app.tabs.len() {
-            kass.app.tabs.remove(to_remove);
-        } else {
-            kass.app.tabs.remove(kass.app.active_index);
-        }
+// `:q N` closes tab N, asking first if it has unsaved changes
+pub fn quit(input: &str, close: &mut bool, kass: &mut Kass) {
+    let index = tab_index(input, kass);
 
-        if kass.app.tabs.len() == 0 {
-            *close = true;
-        } else if kass.app.tabs.len() == kass.app.active_index {
-            kass.app.active_index -= 1;
-        }
+    if kass.app.tabs[index].is_saved() {
+        close_tab(kass, index, close);
     } else {
-        kass.set_error("File not saved")
+        let message = format!("Save changes to {}?", kass.app.tabs[index].title);
+        kass.app.prompt = Some(Prompt::new(message.as_str(), PromptAction::Quit(index)));
     }
 }
 
 pub fn quit_all(_input: &str, close: &mut bool, kass: &mut Kass) {
-    let mut saved = true;
+    confirm_quit_all(kass, 0, close);
+}
 
-    for tab in kass.app.tabs.iter() {
-        if !tab.is_saved() {
-            saved = false;
-            kass.set_error(format!("{} is not saved!", tab.title).as_str());
-            break;
+// asks about the first unsaved tab from `from` on, closes the editor once there is none
+pub fn confirm_quit_all(kass: &mut Kass, from: usize, close: &mut bool) {
+    match (from..kass.app.tabs.len()).find(|i| !kass.app.tabs[*i].is_saved()) {
+        Some(index) => {
+            let message = format!("Save changes to {}?", kass.app.tabs[index].title);
+            kass.app.prompt = Some(Prompt::new(message.as_str(), PromptAction::QuitAll(index)));
         }
+        None => *close = true,
     }
+}
+
+// the tab number given to `:q`, the active tab without one or for one out of range
+fn tab_index(input: &str, kass: &Kass) -> usize {
+    match input.trim().parse::<usize>() {
+        Ok(index) if index < kass.app.tabs.len() => index,
+        _ => kass.app.active_index,
+    }
+}
+
+pub fn close_tab(kass: &mut Kass, index: usize, close: &mut bool) {
+    kass.keep_file_marks(index);
+    kass.app.tabs.remove(index);
 
-    if saved {
+    if kass.app.tabs.is_empty() {
         *close = true;
+    } else if index < kass.app.active_index || kass.app.tabs.len() == kass.app.active_index {
+        kass.app.active_index -= 1;
+    }
+}
+
+pub fn save_tab(kass: &mut Kass, index: usize) -> bool {
+    let format = kass.file_format(index);
+
+    match kass.app.tabs[index].save(&format) {
+        Ok(_) => true,
+        Err(e) => {
+            kass.set_error(format!("{}: {}", kass.app.tabs[index].title, e).as_str());
+            false
+        }
     }
 }
 
@@ -92,26 +133,84 @@ pub fn new_tab(input: &str, _close: &mut bool, kass: &mut Kass) {
             }
         }
 
-        let mut new_editor = Editor::new(filepath.clone()).expect("Couln't create file 1");
-
-        if input != "" {
-            new_editor =
-                Editor::new(input.to_string()).expect("Couldn't create new editor instance");
+        if !input.is_empty() {
+            filepath = input.to_string();
         }
 
-        kass.app.tabs.push(new_editor);
-        kass.app.active_index = kass.app.tabs.len() - 1;
+        match Editor::new(filepath) {
+            Ok(new_editor) => {
+                kass.app.tabs.push(new_editor);
+                kass.app.active_index = kass.app.tabs.len() - 1;
+            }
+            Err(e) => kass.set_error(e.to_string().as_str()),
+        }
     } else {
         kass.set_error("Provide a filepath");
     }
 }
 
-pub fn write(_input: &str, _close: &mut bool, kass: &mut Kass) {
-    match kass.app.tabs[kass.app.active_index].save() {
+pub fn write(input: &str, _close: &mut bool, kass: &mut Kass) {
+    write_buffer(input, kass, false);
+}
+
+pub fn force_write(input: &str, _close: &mut bool, kass: &mut Kass) {
+    write_buffer(input, kass, true);
+}
+
+// `:w`, `:w PATH`, `:w >> PATH` and `:w !cmd`, `force` overwrites existing and read-only files
+fn write_buffer(input: &str, kass: &mut Kass, force: bool) {
+    let input = input.trim();
+    let index = kass.app.active_index;
+
+    if let Some(command) = input.strip_prefix('!') {
+        pipe_to_command(command.trim(), kass);
+    } else if let Some(path) = input.strip_prefix(">>") {
+        let path = match path.trim() {
+            "" => kass.app.tabs[index].filepath.clone(),
+            path => path.to_string(),
+        };
+        let format = kass.file_format(index);
+
+        match kass.app.tabs[index].write_to(path.as_str(), &format, true) {
+            Ok(_) => kass.set_info(format!("{} appended.", path).as_str()),
+            Err(e) => kass.set_error(e.to_string().as_str()),
+        }
+    } else if input.is_empty() || input == kass.app.tabs[index].filepath {
+        save_current(kass, force);
+    } else if let Err(e) = write_new_file(input, kass, force) {
+        kass.set_error(e.to_string().as_str());
+    } else {
+        kass.set_info(format!("{} written.", input).as_str());
+    }
+}
+
+fn save_current(kass: &mut Kass, force: bool) {
+    let index = kass.app.active_index;
+    let filepath = kass.app.tabs[index].filepath.clone();
+    let format = kass.file_format(index);
+
+    let readonly = is_readonly(&filepath);
+
+    if readonly && !force {
+        kass.set_error(
+            format!(
+                "{} is read-only (add ! to override)",
+                kass.app.tabs[index].title
+            )
+            .as_str(),
+        );
+        return;
+    }
+
+    let result = if readonly {
+        with_write_permission(&filepath, || kass.app.tabs[index].save(&format))
+    } else {
+        kass.app.tabs[index].save(&format)
+    };
+
+    match result {
         Ok(_) => {
-            kass.set_info(
-                format!("{} saved.", kass.app.tabs[kass.app.active_index].title).as_str(),
-            );
+            kass.set_info(format!("{} saved.", kass.app.tabs[index].title).as_str());
         }
         Err(e) => {
             kass.set_error(e.to_string().as_str());
@@ -119,49 +218,245 @@ pub fn write(_input: &str, _close: &mut bool, kass: &mut Kass) {
     }
 }
 
-pub fn force_quit(input: &str, close: &mut bool, kass: &mut Kass) {
-    let mut to_remove = kass.app.active_index;
+// the active buffer to a path other than its own, refusing to replace a file unless forced
+fn write_new_file(filepath: &str, kass: &mut Kass, force: bool) -> io::Result<()> {
+    if Path::new(filepath).is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a directory", filepath),
+        ));
+    }
+    if !force && Path::new(filepath).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists (add ! to override)", filepath),
+        ));
+    }
+
+    let format = kass.file_format(kass.app.active_index);
+    let tab = &mut kass.app.tabs[kass.app.active_index];
+
+    if is_readonly(filepath) {
+        with_write_permission(filepath, || tab.write_to(filepath, &format, false))
+    } else {
+        tab.write_to(filepath, &format, false)
+    }
+}
+
+fn is_readonly(filepath: &str) -> bool {
+    metadata(filepath)
+        .map(|metadata| metadata.permissions().readonly())
+        .unwrap_or(false)
+}
+
+// write permission only for the write, which fails for files owned by someone else
+fn with_write_permission(filepath: &str, write: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+    set_writable(filepath, true)?;
+    let written = write();
+    set_writable(filepath, false).and(written)
+}
+
+fn set_writable(filepath: &str, writable: bool) -> io::Result<()> {
+    let mut permissions = metadata(filepath)?.permissions();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = permissions.mode();
+        permissions.set_mode(if writable {
+            mode | 0o200
+        } else {
+            mode & !0o200
+        });
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(!writable);
+
+    set_permissions(filepath, permissions)
+}
+
+// the buffer on the command's stdin, its output in the command line
+fn pipe_to_command(command: &str, kass: &mut Kass) {
+    if command.is_empty() {
+        kass.set_error("Provide a command");
+        return;
+    }
+
+    let format = kass.file_format(kass.app.active_index);
+    let content = Editor::rows_to_file(kass.app.tabs[kass.app.active_index].rows.clone(), &format);
 
-    if let Ok(number) = i32::from_str_radix(input, 10) {
-        to_remove = number as usize;
+    match shell::run(command, Some(content)) {
+        Ok(output) => show_output(kass, &output),
+        Err(e) => kass.set_error(e.to_string().as_str()),
     }
+}
 
-    if to_remove < kass.app.tabs.len() {
-        kass.app.tabs.remove(to_remove);
+fn show_output(kass: &mut Kass, output: &Output) {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if output.status.success() {
+        let lines: Vec<&str> = stdout.lines().chain(stderr.lines()).collect();
+        kass.set_info(lines.join("  ").as_str());
     } else {
-        kass.app.tabs.remove(kass.app.active_index);
+        let code = output
+            .status
+            .code()
+            .map(|code| code.to_string())
+            .unwrap_or_else(|| String::from("a signal"));
+        let lines: Vec<&str> = stderr.lines().collect();
+        kass.set_error(format!("shell returned {}: {}", code, lines.join("  ")).as_str());
     }
+}
 
-    if kass.app.tabs.len() == 0 {
-        *close = true;
-    } else if kass.app.tabs.len() == kass.app.active_index {
-        kass.app.active_index -= 1;
+// leaves the command for the main loop, which needs the terminal to run it
+pub fn run_shell(command: &str, kass: &mut Kass) {
+    if command.is_empty() {
+        kass.set_error("Provide a command");
+    } else {
+        kass.app.shell_command = Some(command.to_string());
     }
 }
 
-pub fn force_quit_all(_input: &str, close: &mut bool, _kass: &mut Kass) {
-    *close = true;
+// the lines in `range` go through the command and its output replaces them
+pub fn filter(range: LineRange, command: &str, kass: &mut Kass) {
+    if command.is_empty() {
+        kass.set_error("Provide a command");
+        return;
+    }
+
+    let start = range.start.max(1) - 1;
+    let mut input = kass.app.tabs[kass.app.active_index].rows[start..range.end].join("\n");
+    input.push('\n');
+
+    if let Some(rows) = command_output(command, Some(input), kass) {
+        let count = range.end - start;
+        kass.app.tabs[kass.app.active_index].replace_rows(start, range.end, rows);
+        goto_line(kass, start + 1);
+        kass.set_info(format!("{} lines filtered", count).as_str());
+    }
 }
 
-pub fn write_all(_input: &str, _close: &mut bool, kass: &mut Kass) {
-    let mut i = 0;
-    loop {
-        match kass.app.tabs[kass.app.active_index].save() {
-            Ok(_) => {
-                kass.set_info(
-                    format!("{} saved.", kass.app.tabs[kass.app.active_index].title).as_str(),
-                );
-            }
+// `:r PATH` and `:r !cmd` put the file or the command's output below the last line of the range,
+// `:0r` above the first line
+pub fn read(range: LineRange, input: &str, _close: &mut bool, kass: &mut Kass) {
+    let input = input.trim();
+
+    let rows = match input.strip_prefix('!') {
+        Some(command) => command_output(command.trim(), None, kass),
+        None if input.is_empty() => {
+            kass.set_error("Provide a filepath or !cmd");
+            None
+        }
+        None if !Path::new(input).is_file() => {
+            kass.set_error(format!("Can't open {}", input).as_str());
+            None
+        }
+        None => match Editor::file_to_rows(input.to_string()) {
+            Ok((rows, _)) => Some(rows),
             Err(e) => {
                 kass.set_error(e.to_string().as_str());
-                break;
+                None
             }
+        },
+    };
+
+    if let Some(rows) = rows {
+        if !rows.is_empty() {
+            kass.app.tabs[kass.app.active_index].replace_rows(range.end, range.end, rows);
+            goto_line(kass, range.end + 1);
         }
-        i += 1;
+    }
+}
+
+// lines the command printed, or nothing after showing why it failed
+fn command_output(command: &str, input: Option<String>, kass: &mut Kass) -> Option<Vec<String>> {
+    if command.is_empty() {
+        kass.set_error("Provide a command");
+        return None;
+    }
 
-        if i == kass.app.tabs.len() {
-            break;
+    match shell::run(command, input) {
+        Ok(output) if output.status.success() => Some(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(String::from)
+                .collect(),
+        ),
+        Ok(output) => {
+            show_output(kass, &output);
+            None
         }
+        Err(e) => {
+            kass.set_error(e.to_string().as_str());
+            None
+        }
+    }
+}
+
+// writes the buffer to a new path and makes it the buffer's file
+pub fn save_as(input: &str, _close: &mut bool, kass: &mut Kass) {
+    let filepath = input.trim();
+
+    if filepath.is_empty() {
+        kass.set_error("Provide a filepath");
+        return;
+    }
+
+    match write_new_file(filepath, kass, false) {
+        Ok(_) => {
+            let tab = &mut kass.app.tabs[kass.app.active_index];
+            tab.rename(filepath.to_string());
+            tab.mark_saved();
+            kass.set_info(format!("{} saved.", kass.app.tabs[kass.app.active_index].title).as_str())
+        }
+        Err(e) => kass.set_error(e.to_string().as_str()),
+    }
+}
+
+pub fn force_quit(input: &str, close: &mut bool, kass: &mut Kass) {
+    let index = tab_index(input, kass);
+    close_tab(kass, index, close);
+}
+
+pub fn force_quit_all(_input: &str, close: &mut bool, _kass: &mut Kass) {
+    *close = true;
+}
+
+// saves every modified tab, going on past the ones that fail
+pub fn write_all(_input: &str, _close: &mut bool, kass: &mut Kass) {
+    let mut written = 0;
+    let mut failed: Vec<String> = vec![];
+
+    for index in 0..kass.app.tabs.len() {
+        if kass.app.tabs[index].is_saved() {
+            continue;
+        }
+
+        let format = kass.file_format(index);
+
+        match kass.app.tabs[index].save(&format) {
+            Ok(_) => written += 1,
+            Err(e) => failed.push(format!("{} ({})", kass.app.tabs[index].title, e)),
+        }
+    }
+
+    let files = |count: usize| if count == 1 { "file" } else { "files" };
+
+    if failed.is_empty() {
+        kass.set_info(format!("{} {} written", written, files(written)).as_str());
+    } else {
+        kass.set_error(
+            format!(
+                "{} {} written, {} failed: {}",
+                written,
+                files(written),
+                failed.len(),
+                failed.join(", ")
+            )
+            .as_str(),
+        );
     }
 }
 
@@ -174,3 +469,213 @@ pub fn write_and_quit_all(input: &str, close: &mut bool, kass: &mut Kass) {
     write_all(input, close, kass);
     quit_all(input, close, kass);
 }
+
+// `:marks`, the active tab's marks and the file marks as `name line:column`
+pub fn show_marks(_input: &str, _close: &mut bool, kass: &mut Kass) {
+    let tab = &kass.app.tabs[kass.app.active_index];
+    let mut local: Vec<(char, marks::Mark)> = tab
+        .marks
+        .iter()
+        .filter(|(name, _)| marks::is_local(**name))
+        .map(|(name, mark)| (*name, *mark))
+        .collect();
+    local.sort_by_key(|(name, _)| *name);
+
+    let mut files: Vec<(char, &String, marks::Mark)> = kass
+        .file_marks
+        .iter()
+        .map(|(name, (filepath, mark))| {
+            // an open file's tab has the mark where its lines moved it
+            let mark = kass
+                .app
+                .tabs
+                .iter()
+                .find_map(|tab| tab.marks.get(name).filter(|_| tab.filepath == *filepath))
+                .copied()
+                .unwrap_or(*mark);
+            (*name, filepath, mark)
+        })
+        .collect();
+    files.sort_by_key(|(name, _, _)| *name);
+
+    let lines: Vec<String> = local
+        .iter()
+        .map(|(name, mark)| format!("{} {}:{}", name, mark.row + 1, mark.col + 1))
+        .chain(files.iter().map(|(name, filepath, mark)| {
+            format!("{} {}:{} {}", name, mark.row + 1, mark.col + 1, filepath)
+        }))
+        .collect();
+
+    if lines.is_empty() {
+        kass.set_info("No marks set");
+    } else {
+        kass.set_info(lines.join("  ").as_str());
+    }
+}
+
+pub fn show_config(input: &str, _close: &mut bool, kass: &mut Kass) {
+    let key = input.trim();
+
+    if key.is_empty() {
+        let settings = kass.config.describe();
+
+        if settings.is_empty() {
+            kass.set_info("No settings loaded");
+        } else {
+            kass.set_info(settings.join("  ").as_str());
+        }
+    } else {
+        let setting = kass
+            .config
+            .get(key)
+            .map(|value| match kass.config.sources.get(key) {
+                Some(source) => format!("{}={} ({})", key, value, source),
+                None => format!("{}={}", key, value),
+            });
+
+        match setting {
+            Some(setting) => kass.set_info(setting.as_str()),
+            None => kass.set_error(format!("{} is not set in the config", key).as_str()),
+        }
+    }
+}
+
+pub fn set(input: &str, _close: &mut bool, kass: &mut Kass) {
+    set_options(input, kass, true, true);
+}
+
+pub fn set_local(input: &str, _close: &mut bool, kass: &mut Kass) {
+    set_options(input, kass, true, false);
+}
+
+pub fn set_global(input: &str, _close: &mut bool, kass: &mut Kass) {
+    set_options(input, kass, false, true);
+}
+
+fn set_options(input: &str, kass: &mut Kass, local: bool, global: bool) {
+    let mut shown: Vec<String> = vec![];
+
+    if input.trim().is_empty() || input.trim() == "all" {
+        let all = input.trim() == "all";
+
+        for def in options::OPTIONS.iter() {
+            let value = kass.option(def.name);
+
+            if all || value != def.default_value() {
+                shown.push(options::describe(def, &value));
+            }
+        }
+
+        if shown.is_empty() {
+            kass.set_info("All options are at their defaults");
+        } else {
+            kass.set_info(shown.join("  ").as_str());
+        }
+        return;
+    }
+
+    for arg in input.split_whitespace() {
+        let parsed = SetArg::parse(arg);
+        let name = match parsed {
+            SetArg::Enable(name)
+            | SetArg::Disable(name)
+            | SetArg::Toggle(name)
+            | SetArg::Query(name)
+            | SetArg::Reset(name)
+            | SetArg::Assign(name, _) => name,
+        };
+
+        let def = match options::find(name) {
+            Some(def) => def,
+            None => {
+                kass.set_error(format!("Unknown option: {}", name).as_str());
+                return;
+            }
+        };
+        let current = kass.option(def.name);
+
+        let value = match (parsed, def.kind) {
+            (SetArg::Enable(_), OptionKind::Bool) => Some(OptionValue::Bool(true)),
+            (SetArg::Disable(_), OptionKind::Bool) => Some(OptionValue::Bool(false)),
+            (SetArg::Toggle(_), OptionKind::Bool) => {
+                Some(OptionValue::Bool(current != OptionValue::Bool(true)))
+            }
+            (SetArg::Enable(_), _) | (SetArg::Query(_), _) => {
+                shown.push(options::describe(def, &current));
+                None
+            }
+            (SetArg::Reset(_), _) => Some(def.default_value()),
+            (SetArg::Assign(_, value), kind) => match OptionValue::parse(kind, value) {
+                Some(value) => Some(value),
+                None => {
+                    kass.set_error(format!("Invalid argument: {}", arg).as_str());
+                    return;
+                }
+            },
+            _ => {
+                kass.set_error(format!("Invalid argument: {}", arg).as_str());
+                return;
+            }
+        };
+
+        if let Some(value) = value {
+            kass.set_option(def, value, local, global);
+        }
+    }
+
+    if !shown.is_empty() {
+        kass.set_info(shown.join("  ").as_str());
+    }
+}
+
+pub fn map(input: &str, _close: &mut bool, kass: &mut Kass) {
+    map_keys(input, kass, Mode::Normal);
+}
+
+pub fn insert_map(input: &str, _close: &mut bool, kass: &mut Kass) {
+    map_keys(input, kass, Mode::Insert);
+}
+
+pub fn unmap(input: &str, _close: &mut bool, kass: &mut Kass) {
+    unmap_keys(input, kass, Mode::Normal);
+}
+
+pub fn insert_unmap(input: &str, _close: &mut bool, kass: &mut Kass) {
+    unmap_keys(input, kass, Mode::Insert);
+}
+
+// `:map` lists, `:map lhs` shows one mapping and `:map lhs action` adds it
+fn map_keys(input: &str, kass: &mut Kass, mode: Mode) {
+    let input = input.trim();
+    let (keys, action) = match input.split_once(char::is_whitespace) {
+        Some((keys, action)) => (keys, action.trim()),
+        None => (input, ""),
+    };
+
+    if action.is_empty() {
+        let prefix = match keymap::parse_keys(keys, &kass.keymap.leader) {
+            Ok(keys) => format!("{} ", keymap::format_keys(&keys)),
+            Err(_) => String::new(),
+        };
+        let mappings: Vec<String> = kass
+            .keymap
+            .list(mode)
+            .into_iter()
+            .filter(|mapping| mapping.starts_with(prefix.as_str()))
+            .collect();
+
+        if mappings.is_empty() {
+            kass.set_info("No mapping found");
+        } else {
+            kass.set_info(mappings.join("  ").as_str());
+        }
+    } else if let Err(e) = kass.map_keys(mode, keys, Some(action)) {
+        kass.set_error(e.as_str());
+    }
+}
+
+fn unmap_keys(input: &str, kass: &mut Kass, mode: Mode) {
+    if let Err(e) = kass.map_keys(mode, input.trim(), None) {
+        kass.set_error(e.as_str());
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    fs::{create_dir_all, read_to_string, write},
+    io::Result,
+    path::PathBuf,
+};
+
+// file in the config directory the command history is kept in between sessions
+pub const HISTORY_FILE: &str = "history";
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub entries: Vec<String>,
+    path: Option<PathBuf>,
+    // entry shown while going through the history with Up/Down
+    browsing: Option<usize>,
+    // what was typed before browsing started, only entries starting with it are shown
+    prefix: String,
+}
+
+impl History {
+    pub fn load(path: PathBuf) -> Result<History> {
+        let entries = if path.is_file() {
+            read_to_string(&path)?
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect()
+        } else {
+            vec![]
+        };
+
+        Ok(History {
+            entries,
+            path: Some(path),
+            ..History::default()
+        })
+    }
+
+    // the newest entry goes last, an older copy of it is dropped
+    pub fn add(&mut self, entry: &str, limit: usize) -> Result<()> {
+        self.reset();
+
+        if entry.trim().is_empty() {
+            return Ok(());
+        }
+
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_string());
+
+        if self.entries.len() > limit {
+            self.entries.drain(..self.entries.len() - limit);
+        }
+
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                create_dir_all(dir)?;
+            }
+
+            let mut content = self.entries.join("\n");
+            content.push('\n');
+            write(path, content)?;
+        }
+
+        Ok(())
+    }
+
+    // stops browsing, the next Up starts from the newest entry again
+    pub fn reset(&mut self) {
+        self.browsing = None;
+        self.prefix.clear();
+    }
+
+    pub fn older(&mut self, typed: &str) -> Option<&str> {
+        let start = match self.browsing {
+            Some(index) => index,
+            None => {
+                self.prefix = typed.to_string();
+                self.entries.len()
+            }
+        };
+
+        let index = (0..start)
+            .rev()
+            .find(|i| self.entries[*i].starts_with(self.prefix.as_str()))?;
+        self.browsing = Some(index);
+
+        Some(self.entries[index].as_str())
+    }
+
+    // past the newest entry the typed text comes back
+    pub fn newer(&mut self) -> Option<&str> {
+        let start = self.browsing?;
+
+        match (start + 1..self.entries.len())
+            .find(|i| self.entries[*i].starts_with(self.prefix.as_str()))
+        {
+            Some(index) => {
+                self.browsing = Some(index);
+                Some(self.entries[index].as_str())
+            }
+            None => {
+                self.browsing = None;
+                Some(self.prefix.as_str())
+            }
+        }
+    }
+}
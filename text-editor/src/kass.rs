@@ -1,16 +1,44 @@
-use std::{format, io::Result, path::Path, vec};
+use std::{
+    collections::{HashMap, VecDeque},
+    format,
+    io::{stdin, stdout, Result, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+    vec,
+};
 
-use crossterm::event::{self, Event, KeyEvent, KeyEventState, KeyModifiers};
+use crossterm::{
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyEvent, KeyEventState, KeyModifiers,
+    },
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
 use serde_json::Value;
 use tui::{backend::Backend, Terminal};
 
 use crate::{
-    editor::Editor,
+    brackets,
+    change::Change,
+    completion::Completion,
+    config::Config,
+    editor::{Editor, FileFormat, Indent},
     enums::*,
+    history::History,
+    keymap::{Key, Keymap},
+    macros::MacroRecording,
+    marks::{self, Mark},
     mode_handlers::{
-        command::handle_command_mode, insert::handle_insert_mode, normal::handle_normal_mode,
+        command::{handle_command_mode, resume_commands, validate_commands},
+        insert::{handle_insert_mode, insert_mode_timeout},
+        normal::{handle_normal_mode, normal_mode_timeout},
+        prompt::handle_prompt,
     },
+    options::{self, OptionDef, OptionValue, Options, Scope},
     position::Position,
+    prompt::Prompt,
+    register::Register,
+    shell,
     ui::ui,
 };
 
@@ -19,11 +47,20 @@ pub struct App {
     pub tabs: Vec<Editor>,
 
     pub command: String,
+    // byte index of the cursor in `command`
+    pub command_cursor: usize,
+    // Tab completion in progress on the command line
+    pub completion: Option<Completion>,
+    // question in the command line that takes the next key
+    pub prompt: Option<Prompt>,
+    // `:!cmd` waiting for the main loop to hand it the terminal
+    pub shell_command: Option<String>,
+    // commands chained after `shell_command`, with their user command depth, run once it's done
+    pub after_shell: Vec<(String, usize)>,
     pub error: String,
     pub info: String,
 
     pub action: CommandAction,
-    pub clipboard: Vec<String>,
     pub active_index: usize,
 }
 
@@ -40,8 +77,12 @@ impl App {
         Ok(App {
             mode: Mode::Normal,
             command: String::new(),
+            command_cursor: 0,
+            completion: None,
+            prompt: None,
+            shell_command: None,
+            after_shell: vec![],
             tabs: vec![Editor::new(filepath.clone())?],
-            clipboard: vec![],
             active_index: 0,
             error: String::new(),
             info: String::new(),
@@ -66,10 +107,43 @@ pub struct Kass {
     pub key_event: KeyEvent,
     pub cursor: Position,
     pub editor_size: (u16, u16),
+    // count typed before a normal-mode command
     pub buf: String,
+    // keys so far of a mapping that isn't complete yet
+    pub pending_keys: Vec<Key>,
+    pub keymap: Keymap,
+    pub history: History,
+    // action like `r` waiting for the character typed after it, with its count
+    pub awaiting_char: Option<(String, usize)>,
+    pub register: Register,
+    // the change being made and the last one done, for `.`
+    pub recording: Option<Change>,
+    pub last_change: Option<Change>,
+    // the macro being recorded with `q{reg}`, the recorded ones and the one `@@` plays
+    pub macro_recording: Option<MacroRecording>,
+    pub macros: HashMap<char, Vec<KeyEvent>>,
+    pub last_macro: Option<char>,
+    // keys `@{reg}` plays back before any is read from the terminal, each with how many
+    // macros deep it was queued, and that depth for the key being handled
+    pub replay: VecDeque<(KeyEvent, usize)>,
+    pub replay_depth: usize,
+    // `A` to `Z` with their file, kept up to date from the tab's own marks while it is open
+    pub file_marks: HashMap<char, (String, Mark)>,
+    // `Ctrl-o` in insert or replace mode, back to it after one normal-mode command
+    pub return_to: Option<Mode>,
+    // characters `R` typed over on the cursor line, none where it added one, for Backspace
+    pub replaced: Vec<Option<char>>,
 
     // settings
-    pub line_number: LineNumber,
+    // openers `autopairs` pairs per filetype, from the config, instead of the defaults
+    pub auto_pairs: HashMap<String, String>,
+    pub options: Options,
+    pub config: Config,
+    // what `:set` made global and `:map`/`:unmap` changed, kept over a reloaded config
+    runtime_options: Options,
+    runtime_mappings: Vec<(Mode, String, Option<String>)>,
+    // where the user config and history live
+    pub config_dir: PathBuf,
 }
 
 impl Kass {
@@ -86,64 +160,430 @@ impl Kass {
             cursor: Position::new(),
             editor_size: (0, 0),
             buf: String::new(),
+            pending_keys: vec![],
+            keymap: Keymap::new(),
+            history: History::default(),
+            awaiting_char: None,
+            register: Register::default(),
+            recording: None,
+            last_change: None,
+            macro_recording: None,
+            macros: HashMap::new(),
+            last_macro: None,
+            replay: VecDeque::new(),
+            replay_depth: 0,
+            file_marks: HashMap::new(),
+            return_to: None,
+            replaced: vec![],
 
-            line_number: LineNumber::None,
+            auto_pairs: HashMap::new(),
+            options: Options::with_defaults(),
+            config: Config::new(),
+            runtime_options: Options::default(),
+            runtime_mappings: vec![],
+            config_dir: PathBuf::new(),
         })
     }
 
     pub fn run<B: Backend>(
         &mut self,
         terminal: &mut Terminal<B>,
-        config: Option<Value>,
+        config: Option<Config>,
     ) -> Result<()> {
         if let Some(config) = config {
             let mut close = false;
+            self.config = config;
+            self.apply_config();
 
-            if let Value::Object(settings) = &config {
-                for (key, value) in settings.iter() {
-                    match key.as_str() {
-                        "line_number" => match value.as_str() {
-                            Some(value) => match value {
-                                "none" => self.line_number = LineNumber::None,
-                                "absolute" => self.line_number = LineNumber::Absolute,
-                                "relative" => self.line_number = LineNumber::Relative,
-                                _ => {
-                                    self.set_error("Provide a valid value for line number");
-                                }
-                            },
-                            None => {
-                                self.set_error("Provide a value for line number");
+            loop {
+                self.show_warnings();
+                self.sync_config();
+                let settings = self.config.settings.clone();
+
+                terminal.draw(|f| ui(self, f))?;
+
+                // a mapping that another key could still extend runs once none comes in time
+                let timeout = Duration::from_millis(self.option_number("timeoutlen") as u64);
+                let key = if let Some((key, depth)) = self.replay.pop_front() {
+                    self.replay_depth = depth;
+                    Some(key)
+                } else if !self.pending_keys.is_empty() && !event::poll(timeout)? {
+                    match self.app.mode {
+                        Mode::Normal => normal_mode_timeout(self, &mut close, &settings),
+                        Mode::Insert | Mode::Replace => insert_mode_timeout(self)?,
+                        Mode::Command => self.pending_keys.clear(),
+                    }
+                    None
+                } else if let Event::Key(key) = event::read()? {
+                    self.replay_depth = 0;
+                    Some(key)
+                } else {
+                    None
+                };
+
+                if let Some(key) = key {
+                    self.handle_key(key, &mut close, &settings)?;
+                }
+
+                while let Some(command) = self.app.shell_command.take() {
+                    self.run_in_terminal(terminal, command.as_str())?;
+                    resume_commands(self, &mut close, &settings);
+                }
+
+                if close {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the settings of `self.config` over the defaults, then what was changed at runtime
+    fn apply_config(&mut self) {
+        let settings = self.config.settings.clone();
+        self.options = Options::with_defaults();
+        self.keymap = Keymap::new();
+        self.auto_pairs.clear();
+
+        if let Value::Object(settings) = &settings {
+            for (key, value) in settings.iter() {
+                match key.as_str() {
+                    "line_number" => match value.as_str() {
+                        Some(value) => match value {
+                            "none" => self.set_line_number(false, false),
+                            "absolute" => self.set_line_number(true, false),
+                            "relative" => self.set_line_number(true, true),
+                            _ => {
+                                self.set_error("Provide a valid value for line number");
                             }
                         },
-                        "command_mode" => {}
-                        key => {
-                            self.set_error(format!("{} in the config doesn't exist", key).as_str());
+                        None => {
+                            self.set_error("Provide a value for line number");
+                        }
+                    },
+                    "options" => {
+                        if let Value::Object(values) = value {
+                            for (name, value) in values.iter() {
+                                match options::find(name) {
+                                    Some(def) => match OptionValue::from_json(def.kind, value) {
+                                        Some(value) => self.options.set(def, value),
+                                        None => self.set_error(
+                                            format!("Invalid value for option {}", name).as_str(),
+                                        ),
+                                    },
+                                    None => {
+                                        self.set_error(format!("Unknown option: {}", name).as_str())
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    "autopairs" => {
+                        if let Value::Object(filetypes) = value {
+                            for (filetype, openers) in filetypes.iter() {
+                                match openers.as_str() {
+                                    Some(openers)
+                                        if openers
+                                            .chars()
+                                            .all(|ch| brackets::closer(ch).is_some()) =>
+                                    {
+                                        self.auto_pairs
+                                            .insert(filetype.clone(), openers.to_string());
+                                    }
+                                    _ => self.set_error(
+                                        format!("Invalid autopairs for {}", filetype).as_str(),
+                                    ),
+                                }
+                            }
+                        }
+                    }
+                    "keymap" => {
+                        for error in self.keymap.load_config(value) {
+                            self.set_error(error.as_str());
                         }
                     }
+                    "command_mode" | "user_commands" => {}
+                    key => {
+                        self.set_error(format!("{} in the config doesn't exist", key).as_str());
+                    }
                 }
             }
+        }
 
-            loop {
-                terminal.draw(|f| ui(self, f))?;
+        for error in validate_commands(&settings) {
+            self.set_error(error.as_str());
+        }
 
-                if let Event::Key(key) = event::read()? {
-                    self.key_event = key;
+        self.options.extend(&self.runtime_options);
+        for (mode, keys, action) in self.runtime_mappings.iter() {
+            let _ = match action {
+                Some(action) => self.keymap.map(*mode, keys, action),
+                None => self.keymap.unmap(*mode, keys),
+            };
+        }
+    }
 
-                    match self.app.mode {
-                        Mode::Normal => handle_normal_mode(self)?,
-                        Mode::Command => handle_command_mode(self, &mut close, &config)?,
-                        Mode::Insert => handle_insert_mode(self)?,
-                    }
-                }
+    // problems the tabs met opening their files
+    fn show_warnings(&mut self) {
+        let warnings: Vec<String> = self
+            .app
+            .tabs
+            .iter_mut()
+            .flat_map(|tab| tab.warnings.drain(..))
+            .collect();
 
-                if close {
-                    break;
+        if !warnings.is_empty() {
+            self.set_error(warnings.join("  ").as_str());
+        }
+    }
+
+    // the config follows the active tab, reloaded when its file is in another project
+    fn sync_config(&mut self) {
+        let tab = &self.app.tabs[self.app.active_index];
+
+        if tab.filepath.is_empty() || tab.project_config == self.config.project {
+            return;
+        }
+
+        let project = tab.project_config.clone();
+        match Config::load(&self.config_dir, Path::new(&tab.filepath)) {
+            Ok(config) => {
+                self.config = config;
+                self.apply_config();
+            }
+            Err(e) => {
+                // not tried again until another project's file is active
+                self.config.project = project;
+                self.set_error(e.to_string().as_str());
+            }
+        }
+    }
+
+    // a key typed or played back from a macro, typed ones go in a macro being recorded
+    pub fn handle_key(&mut self, key: KeyEvent, close: &mut bool, settings: &Value) -> Result<()> {
+        self.key_event = key;
+
+        if let Some(recording) = self
+            .macro_recording
+            .as_mut()
+            .filter(|_| self.replay_depth == 0)
+        {
+            if self.pending_keys.is_empty() {
+                recording.sequence_start = recording.keys.len();
+            }
+            recording.keys.push(key);
+        }
+
+        let returning = self.return_to;
+
+        if self.app.prompt.is_some() {
+            handle_prompt(self, close)?;
+        } else {
+            match self.app.mode {
+                Mode::Normal => handle_normal_mode(self, close, settings)?,
+                Mode::Command => handle_command_mode(self, close, settings)?,
+                Mode::Insert | Mode::Replace => handle_insert_mode(self)?,
+            }
+        }
+
+        // the command after `Ctrl-o` is done once nothing more is waited for
+        if let Some(mode) = returning {
+            match self.app.mode {
+                Mode::Insert | Mode::Replace => self.return_to = None,
+                Mode::Normal
+                    if self.pending_keys.is_empty()
+                        && self.awaiting_char.is_none()
+                        && self.buf.is_empty() =>
+                {
+                    self.return_to = None;
+                    self.app.mode = mode;
                 }
+                _ => {}
             }
         }
+
+        Ok(())
+    }
+
+    // steps out of the UI so the command's output stays on screen until Enter
+    fn run_in_terminal<B: Backend>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        command: &str,
+    ) -> Result<()> {
+        disable_raw_mode()?;
+        execute!(stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+
+        match shell::run_in_terminal(command) {
+            Ok(status) if !status.success() => match status.code() {
+                Some(code) => println!("\nshell returned {}", code),
+                None => println!("\nshell command was stopped"),
+            },
+            Ok(_) => {}
+            Err(e) => println!("{}", e),
+        }
+
+        print!("\nPress ENTER to continue");
+        stdout().flush()?;
+        stdin().read_line(&mut String::new())?;
+
+        execute!(stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        enable_raw_mode()?;
+        terminal.clear()
+    }
+
+    // buffer-local value of the active tab if set, the global one otherwise
+    pub fn option(&self, name: &str) -> OptionValue {
+        self.tab_option(self.app.active_index, name)
+    }
+
+    pub fn tab_option(&self, index: usize, name: &str) -> OptionValue {
+        let def = options::find(name).expect("unknown option");
+
+        self.app.tabs[index]
+            .options
+            .get(def.name)
+            .or_else(|| self.options.get(def.name))
+            .cloned()
+            .unwrap_or_else(|| def.default_value())
+    }
+
+    pub fn option_bool(&self, name: &str) -> bool {
+        matches!(self.option(name), OptionValue::Bool(true))
+    }
+
+    pub fn option_number(&self, name: &str) -> usize {
+        match self.option(name) {
+            OptionValue::Number(value) => value,
+            _ => 0,
+        }
+    }
+
+    // global-scoped options ignore `local` since there is nothing to override per tab
+    pub fn set_option(
+        &mut self,
+        def: &'static OptionDef,
+        value: OptionValue,
+        local: bool,
+        global: bool,
+    ) {
+        if def.scope == Scope::Global || global {
+            self.options.set(def, value.clone());
+            self.runtime_options.set(def, value.clone());
+        }
+        if def.scope == Scope::Buffer && local {
+            self.app.tabs[self.app.active_index].options.set(def, value);
+        }
+    }
+
+    pub fn shiftwidth(&self) -> usize {
+        match self.option_number("shiftwidth") {
+            0 => self.option_number("tabstop"),
+            width => width,
+        }
+    }
+
+    pub fn softtabstop(&self) -> usize {
+        match self.option_number("softtabstop") {
+            0 => self.shiftwidth(),
+            width => width,
+        }
+    }
+
+    // openers and closers typed together in the current tab, none with `noautopairs`
+    pub fn auto_pairs(&self) -> Vec<(char, char)> {
+        if !self.option_bool("autopairs") {
+            return vec![];
+        }
+
+        let filetype = self.app.tabs[self.app.active_index].filetype();
+        let openers = match self.auto_pairs.get(filetype) {
+            Some(openers) => openers.as_str(),
+            None => brackets::default_auto_pairs(filetype),
+        };
+
+        openers
+            .chars()
+            .filter_map(|ch| brackets::closer(ch).map(|close| (ch, close)))
+            .collect()
+    }
+
+    pub fn indent(&self) -> Indent {
+        Indent {
+            auto: self.option_bool("autoindent"),
+            smart: self.option_bool("smartindent"),
+            shiftwidth: self.shiftwidth(),
+            tabstop: self.option_number("tabstop"),
+            expandtab: self.option_bool("expandtab"),
+        }
+    }
+
+    pub fn file_format(&self, index: usize) -> FileFormat {
+        let text = |name| match self.tab_option(index, name) {
+            OptionValue::Text(value) => value,
+            value => value.to_string(),
+        };
+        let flag = |name| matches!(self.tab_option(index, name), OptionValue::Bool(true));
+
+        FileFormat {
+            line_ending: match text("fileformat").as_str() {
+                "dos" => "\r\n",
+                "mac" => "\r",
+                _ => "\n",
+            },
+            encoding: text("fileencoding"),
+            trim_trailing_whitespace: flag("trimtrailingwhitespace"),
+            final_newline: flag("fixendofline"),
+        }
+    }
+
+    pub fn line_number(&self) -> LineNumber {
+        match (
+            self.option_bool("number"),
+            self.option_bool("relativenumber"),
+        ) {
+            (_, true) => LineNumber::Relative,
+            (true, false) => LineNumber::Absolute,
+            (false, false) => LineNumber::None,
+        }
+    }
+
+    fn set_line_number(&mut self, number: bool, relative: bool) {
+        for (name, value) in [("number", number), ("relativenumber", relative)] {
+            if let Some(def) = options::find(name) {
+                self.options.set(def, OptionValue::Bool(value));
+            }
+        }
+    }
+
+    // `:map` with an action and `:unmap` without one
+    pub fn map_keys(
+        &mut self,
+        mode: Mode,
+        keys: &str,
+        action: Option<&str>,
+    ) -> std::result::Result<(), String> {
+        match action {
+            Some(action) => self.keymap.map(mode, keys, action)?,
+            None => self.keymap.unmap(mode, keys)?,
+        }
+
+        self.runtime_mappings
+            .push((mode, keys.to_string(), action.map(String::from)));
         Ok(())
     }
 
+    // the file marks of a tab about to close or load another file, where its lines moved them
+    pub fn keep_file_marks(&mut self, index: usize) {
+        let tab = &self.app.tabs[index];
+
+        for (name, mark) in tab.marks.iter() {
+            if marks::is_file_mark(*name) {
+                self.file_marks.insert(*name, (tab.filepath.clone(), *mark));
+            }
+        }
+    }
+
     pub fn set_info(&mut self, info: &str) {
         self.app.action = CommandAction::Info;
         self.app.info = info.to_string();
@@ -153,4 +593,4 @@ impl Kass {
         self.app.action = CommandAction::Error;
         self.app.error = error.to_string();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde_json::Value;
+
+use crate::enums::Mode;
+
+// what a normal-mode mapping can run, besides `:command` lines
+pub const NORMAL_ACTIONS: &[&str] = &[
+    "insert",
+    "append",
+    "open_below",
+    "open_above",
+    "command_mode",
+    "left",
+    "down",
+    "up",
+    "right",
+    "next_tab",
+    "prev_tab",
+    "clear",
+    "command_history",
+    "enter",
+    "delete_line",
+    "yank_line",
+    "join",
+    "delete_char",
+    "replace_char",
+    "toggle_case",
+    "put_after",
+    "put_before",
+    "delete",
+    "change",
+    "yank",
+    "repeat",
+    "record_macro",
+    "play_macro",
+    "set_mark",
+    "goto_mark",
+    "goto_mark_line",
+    "jump_back",
+    "jump_forward",
+    "first_line",
+    "last_line",
+    "page_down",
+    "page_up",
+    "half_page_down",
+    "half_page_up",
+    "screen_top",
+    "screen_middle",
+    "screen_bottom",
+    "scroll_top",
+    "scroll_middle",
+    "scroll_bottom",
+    "line_start",
+    "line_end",
+    "replace_mode",
+    "add_cursor_next_match",
+    "add_cursor_below",
+    "add_cursor_above",
+    "matching_bracket",
+    "undo",
+    "visual_block",
+    "block_insert",
+    "block_append",
+];
+
+pub const INSERT_ACTIONS: &[&str] = &[
+    "normal",
+    "newline",
+    "backspace",
+    "tab",
+    "left",
+    "right",
+    "up",
+    "down",
+    "line_start",
+    "line_end",
+    "delete_char",
+    "delete_word",
+    "delete_to_line_start",
+    "insert_register",
+    "normal_command",
+];
+
+const DEFAULT_NORMAL: &[(&str, &str)] = &[
+    ("i", "insert"),
+    ("a", "append"),
+    ("o", "open_below"),
+    ("O", "open_above"),
+    (":", "command_mode"),
+    ("h", "left"),
+    ("j", "down"),
+    ("k", "up"),
+    ("l", "right"),
+    ("<Left>", "left"),
+    ("<Down>", "down"),
+    ("<Up>", "up"),
+    ("<Right>", "right"),
+    ("<Tab>", "next_tab"),
+    ("<S-Tab>", "prev_tab"),
+    ("<Esc>", "clear"),
+    ("q:", "command_history"),
+    ("<CR>", "enter"),
+    ("dd", "delete_line"),
+    ("yy", "yank_line"),
+    ("J", "join"),
+    ("x", "delete_char"),
+    ("<Del>", "delete_char"),
+    ("r", "replace_char"),
+    ("~", "toggle_case"),
+    ("p", "put_after"),
+    ("P", "put_before"),
+    ("d", "delete"),
+    ("c", "change"),
+    ("y", "yank"),
+    (".", "repeat"),
+    ("q", "record_macro"),
+    ("@", "play_macro"),
+    ("m", "set_mark"),
+    ("`", "goto_mark"),
+    ("'", "goto_mark_line"),
+    ("<C-o>", "jump_back"),
+    ("<C-i>", "jump_forward"),
+    ("gg", "first_line"),
+    ("G", "last_line"),
+    ("<C-f>", "page_down"),
+    ("<C-b>", "page_up"),
+    ("<PageDown>", "page_down"),
+    ("<PageUp>", "page_up"),
+    ("<C-d>", "half_page_down"),
+    ("<C-u>", "half_page_up"),
+    ("H", "screen_top"),
+    ("M", "screen_middle"),
+    ("L", "screen_bottom"),
+    ("zt", "scroll_top"),
+    ("zz", "scroll_middle"),
+    ("zb", "scroll_bottom"),
+    ("0", "line_start"),
+    ("<Home>", "line_start"),
+    ("$", "line_end"),
+    ("<End>", "line_end"),
+    ("R", "replace_mode"),
+    ("gb", "add_cursor_next_match"),
+    ("<C-Down>", "add_cursor_below"),
+    ("<C-Up>", "add_cursor_above"),
+    ("%", "matching_bracket"),
+    ("u", "undo"),
+    ("<C-v>", "visual_block"),
+    ("I", "block_insert"),
+    ("A", "block_append"),
+];
+
+const DEFAULT_INSERT: &[(&str, &str)] = &[
+    ("<Esc>", "normal"),
+    ("<CR>", "newline"),
+    ("<BS>", "backspace"),
+    ("<Tab>", "tab"),
+    ("<Left>", "left"),
+    ("<Right>", "right"),
+    ("<Up>", "up"),
+    ("<Down>", "down"),
+    ("<Home>", "line_start"),
+    ("<End>", "line_end"),
+    ("<Del>", "delete_char"),
+    ("<C-w>", "delete_word"),
+    ("<C-u>", "delete_to_line_start"),
+    ("<C-r>", "insert_register"),
+    ("<C-o>", "normal_command"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    // shift is already part of the character (or of BackTab) so it is dropped there
+    pub fn from_event(event: &KeyEvent) -> Key {
+        let modifiers = match event.code {
+            KeyCode::Char(_) | KeyCode::BackTab => event.modifiers - KeyModifiers::SHIFT,
+            _ => event.modifiers,
+        };
+
+        Key {
+            code: event.code,
+            modifiers,
+        }
+    }
+
+    fn plain(code: KeyCode) -> Key {
+        Key {
+            code,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+}
+
+// `<C-w>`, `<leader>`, `<S-Tab>`, `gg` and the like
+pub fn parse_keys(notation: &str, leader: &[Key]) -> Result<Vec<Key>, String> {
+    let mut keys = vec![];
+    let mut rest = notation;
+
+    while let Some(ch) = rest.chars().next() {
+        let special = rest
+            .strip_prefix('<')
+            .and_then(|inner| inner.split_once('>'))
+            .filter(|(name, _)| !name.is_empty() && !name.contains('<'));
+
+        match special {
+            Some((name, after)) => {
+                if name.eq_ignore_ascii_case("leader") {
+                    keys.extend_from_slice(leader);
+                } else {
+                    keys.push(parse_special(name)?);
+                }
+                rest = after;
+            }
+            None => {
+                keys.push(Key::plain(KeyCode::Char(ch)));
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+
+    if keys.is_empty() {
+        return Err(String::from("Provide keys to map"));
+    }
+
+    Ok(keys)
+}
+
+fn parse_special(name: &str) -> Result<Key, String> {
+    let invalid = || format!("Unknown key <{}>", name);
+    let mut modifiers = KeyModifiers::NONE;
+    let mut base = name;
+
+    while let Some((modifier, rest)) = base.split_once('-').filter(|(_, rest)| !rest.is_empty()) {
+        match modifier.to_ascii_uppercase().as_str() {
+            "C" => modifiers |= KeyModifiers::CONTROL,
+            "A" | "M" => modifiers |= KeyModifiers::ALT,
+            "S" => modifiers |= KeyModifiers::SHIFT,
+            _ => return Err(invalid()),
+        }
+        base = rest;
+    }
+
+    let code = match base.to_ascii_lowercase().as_str() {
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" if modifiers.contains(KeyModifiers::SHIFT) => {
+            modifiers -= KeyModifiers::SHIFT;
+            KeyCode::BackTab
+        }
+        "tab" => KeyCode::Tab,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "del" | "delete" => KeyCode::Delete,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "bar" => KeyCode::Char('|'),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "insert" => KeyCode::Insert,
+        lower => match (lower.strip_prefix('f'), base.chars().count()) {
+            (Some(number), _) if !number.is_empty() => {
+                KeyCode::F(number.parse().map_err(|_| invalid())?)
+            }
+            (_, 1) => {
+                let ch = base.chars().next().unwrap_or(' ');
+                // shift is part of the character
+                if modifiers.contains(KeyModifiers::SHIFT) {
+                    modifiers -= KeyModifiers::SHIFT;
+                    KeyCode::Char(ch.to_ascii_uppercase())
+                } else {
+                    KeyCode::Char(ch)
+                }
+            }
+            _ => return Err(invalid()),
+        },
+    };
+
+    Ok(Key { code, modifiers })
+}
+
+pub fn format_keys(keys: &[Key]) -> String {
+    keys.iter().map(format_key).collect()
+}
+
+fn format_key(key: &Key) -> String {
+    let name = match key.code {
+        KeyCode::Char(' ') => String::from("Space"),
+        KeyCode::Char('<') => String::from("lt"),
+        KeyCode::Char(ch) if key.modifiers.is_empty() => return ch.to_string(),
+        KeyCode::Char(ch) => ch.to_string(),
+        KeyCode::Enter => String::from("CR"),
+        KeyCode::Esc => String::from("Esc"),
+        KeyCode::Tab => String::from("Tab"),
+        KeyCode::BackTab => String::from("S-Tab"),
+        KeyCode::Backspace => String::from("BS"),
+        KeyCode::Delete => String::from("Del"),
+        KeyCode::F(number) => format!("F{}", number),
+        code => format!("{:?}", code),
+    };
+
+    let mut prefix = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("A-");
+    }
+    if key.modifiers.contains(KeyModifiers::SHIFT) {
+        prefix.push_str("S-");
+    }
+
+    format!("<{}{}>", prefix, name)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+    // the keys are a whole mapping and nothing longer starts with them
+    Action(String),
+    // the keys start a longer mapping, with the action to run if no more keys come in time
+    Pending(Option<String>),
+    None,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    pub leader: Vec<Key>,
+    maps: HashMap<Mode, Vec<(Vec<Key>, String)>>,
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        let mut keymap = Keymap {
+            leader: vec![Key::plain(KeyCode::Char('\\'))],
+            maps: HashMap::new(),
+        };
+
+        for (mode, defaults) in [
+            (Mode::Normal, DEFAULT_NORMAL),
+            (Mode::Insert, DEFAULT_INSERT),
+        ] {
+            for (keys, action) in defaults.iter() {
+                if let Ok(keys) = parse_keys(keys, &[]) {
+                    keymap.insert(mode, keys, action.to_string());
+                }
+            }
+        }
+
+        keymap
+    }
+
+    fn insert(&mut self, mode: Mode, keys: Vec<Key>, action: String) {
+        let maps = self.maps.entry(mode).or_default();
+
+        match maps.iter_mut().find(|(lhs, _)| *lhs == keys) {
+            Some((_, rhs)) => *rhs = action,
+            None => maps.push((keys, action)),
+        }
+    }
+
+    // `action` is one of the mode's actions or, in normal mode, a `:command`
+    pub fn map(&mut self, mode: Mode, keys: &str, action: &str) -> Result<(), String> {
+        let valid = match mode {
+            Mode::Normal => NORMAL_ACTIONS.contains(&action) || action.starts_with(':'),
+            Mode::Insert => INSERT_ACTIONS.contains(&action),
+            _ => false,
+        };
+
+        if !valid {
+            return Err(format!("{} is not an action in {:?} mode", action, mode));
+        }
+
+        let keys = parse_keys(keys, &self.leader)?;
+        self.insert(mode, keys, action.to_string());
+
+        Ok(())
+    }
+
+    pub fn unmap(&mut self, mode: Mode, keys: &str) -> Result<(), String> {
+        let keys = parse_keys(keys, &self.leader)?;
+        let maps = self.maps.entry(mode).or_default();
+        let count = maps.len();
+
+        maps.retain(|(lhs, _)| *lhs != keys);
+
+        if maps.len() == count {
+            Err(format!("No mapping for {}", format_keys(&keys)))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn lookup(&self, mode: Mode, keys: &[Key]) -> Lookup {
+        let maps = match self.maps.get(&mode) {
+            Some(maps) => maps,
+            None => return Lookup::None,
+        };
+
+        let exact = maps
+            .iter()
+            .find(|(lhs, _)| lhs == keys)
+            .map(|(_, action)| action.clone());
+        let longer = maps
+            .iter()
+            .any(|(lhs, _)| lhs.len() > keys.len() && lhs.starts_with(keys));
+
+        match (exact, longer) {
+            (exact, true) => Lookup::Pending(exact),
+            (Some(action), false) => Lookup::Action(action),
+            (None, false) => Lookup::None,
+        }
+    }
+
+    pub fn list(&self, mode: Mode) -> Vec<String> {
+        self.maps
+            .get(&mode)
+            .map(|maps| {
+                maps.iter()
+                    .map(|(keys, action)| format!("{} {}", format_keys(keys), action))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // `{ "leader": " ", "normal": { "<leader>w": ":w" }, "insert": { "jk": "normal" } }`
+    pub fn load_config(&mut self, config: &Value) -> Vec<String> {
+        let mut errors = vec![];
+
+        if let Some(leader) = config["leader"].as_str() {
+            match parse_keys(leader, &[]) {
+                Ok(keys) => self.leader = keys,
+                Err(e) => errors.push(e),
+            }
+        }
+
+        for (name, mode) in [("normal", Mode::Normal), ("insert", Mode::Insert)] {
+            if let Value::Object(maps) = &config[name] {
+                for (keys, action) in maps.iter() {
+                    let result = match action.as_str() {
+                        Some(action) => self.map(mode, keys, action),
+                        None => Err(format!("Mapping for {} must be a string", keys)),
+                    };
+
+                    if let Err(e) = result {
+                        errors.push(e);
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+}
@@ -0,0 +1,40 @@
+use crossterm::event::{KeyCode, KeyEvent};
+
+// `@@` and macros playing macros stop this many levels deep, a macro that plays itself would never end
+pub const MAX_MACRO_DEPTH: usize = 20;
+
+// `q{reg}` being recorded: the register, the keys typed since and how many of them came
+// before the key sequence being handled, so the `q` that stops it is left out
+#[derive(Debug, Clone)]
+pub struct MacroRecording {
+    pub register: char,
+    pub keys: Vec<KeyEvent>,
+    pub sequence_start: usize,
+}
+
+impl MacroRecording {
+    pub fn new(register: char) -> MacroRecording {
+        MacroRecording {
+            register,
+            keys: vec![],
+            sequence_start: 0,
+        }
+    }
+}
+
+// the text a recorded macro types, for `Ctrl-r` in insert mode
+pub fn typed_text(keys: &[KeyEvent]) -> String {
+    keys.iter()
+        .filter_map(|key| match key.code {
+            KeyCode::Char(ch) => Some(ch),
+            KeyCode::Enter => Some('\n'),
+            KeyCode::Tab => Some('\t'),
+            _ => None,
+        })
+        .collect()
+}
+
+// registers `a` to `z` and `0` to `9`, an uppercase letter adds to the lowercase one
+pub fn is_register(ch: char) -> bool {
+    ch.is_ascii_alphanumeric()
+}
@@ -1,4 +1,8 @@
-use std::{env, fs::read_to_string, io::stdout, path::PathBuf};
+use std::{
+    env,
+    io::stdout,
+    path::{Path, PathBuf},
+};
 
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
@@ -6,16 +10,32 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 
+use config::Config;
+use history::{History, HISTORY_FILE};
 use kass::Kass;
-use serde_json::Value;
 use tui::{backend::CrosstermBackend, Terminal};
 
+mod brackets;
+mod change;
+mod completion;
+mod config;
 mod editor;
+mod editorconfig;
 mod enums;
 mod functions;
+mod history;
 mod kass;
+mod keymap;
+mod macros;
+mod marks;
 mod mode_handlers;
+mod options;
 mod position;
+mod prompt;
+mod range;
+mod register;
+mod shell;
+mod text_object;
 mod ui;
 
 fn main() {
@@ -48,20 +68,12 @@ fn main() {
         } else {
             panic!("Unsupported operating system.");
         };
-        // Create the full path for the configuration file
-        let config_file = config_dir.join("config.json");
+        editor.config_dir = config_dir.clone();
 
-        // parse json file
-        let config_string = match read_to_string(config_file) {
-            Ok(content) => content,
-            Err(e) => {
-                editor.set_error(e.to_string().as_str());
+        // user config from the config directory, project config from the opened file upwards
+        let filepath = editor.app.tabs[editor.app.active_index].filepath.clone();
 
-                "".to_string()
-            }
-        };
-
-        let config_parsed: Option<Value> = match serde_json::from_str(config_string.as_str()) {
+        let config_parsed = match Config::load(&config_dir, Path::new(&filepath)) {
             Ok(conf) => Some(conf),
             Err(e) => {
                 editor.set_error(e.to_string().as_str());
@@ -70,6 +82,11 @@ fn main() {
             }
         };
 
+        match History::load(config_dir.join(HISTORY_FILE)) {
+            Ok(history) => editor.history = history,
+            Err(e) => editor.set_error(e.to_string().as_str()),
+        }
+
         match enable_raw_mode() {
             Ok(_) => {}
             Err(e) => editor.set_error(e.to_string().as_str()),
@@ -0,0 +1,30 @@
+// where a mark or a jump list entry points: a row and the byte column in it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mark {
+    pub row: usize,
+    pub col: usize,
+}
+
+// `a` to `z` belong to a buffer, `'` is the position before the last jump
+pub fn is_local(ch: char) -> bool {
+    ch.is_ascii_lowercase() || ch == '\''
+}
+
+// `A` to `Z` remember their file and can be jumped to from any tab
+pub fn is_file_mark(ch: char) -> bool {
+    ch.is_ascii_uppercase()
+}
+
+// `mark` after rows `at..at + removed` were replaced by `added` rows, none if its row is gone
+pub fn moved(mark: Mark, at: usize, removed: usize, added: usize) -> Option<Mark> {
+    if mark.row < at + removed.min(added) {
+        Some(mark)
+    } else if mark.row >= at + removed {
+        Some(Mark {
+            row: mark.row - removed + added,
+            col: mark.col,
+        })
+    } else {
+        None
+    }
+}
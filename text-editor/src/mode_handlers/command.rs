@@ -1,120 +1,352 @@
-use std::io::Result;
+use std::{io::Result, ops::Range};
 
-use crossterm::event::{self, KeyCode};
+use crossterm::event::{KeyCode, KeyModifiers};
 use serde_json::Value;
 
 use crate::functions::{self, goto_line};
-use crate::{enums::Mode, kass::Kass};
+use crate::{
+    completion::Completion,
+    enums::{CommandAction, Mode},
+    kass::Kass,
+    range::{self, LineRange},
+};
 
-pub fn handle_command_mode(kass: &mut Kass, close: &mut bool, config: &Value) -> Result<()> {
-    let mut prefix_with_function_list: Vec<(&str, fn(&str, &mut bool, &mut Kass))> = vec![];
+type CommandFn = fn(&str, &mut bool, &mut Kass);
+// commands that work on lines, given the range typed before them or the cursor line
+type RangeCommandFn = fn(LineRange, &str, &mut bool, &mut Kass);
+
+// config key in `command_mode` -> function it names
+const COMMANDS: &[(&str, CommandFn)] = &[
+    ("edit_file", functions::edit_file),
+    ("quit", functions::quit),
+    ("quit_all", functions::quit_all),
+    ("new_tab", functions::new_tab),
+    ("write", functions::write),
+    ("force_write", functions::force_write),
+    ("save_as", functions::save_as),
+    ("force_quit", functions::force_quit),
+    ("force_quit_all", functions::force_quit_all),
+    ("write_all", functions::write_all),
+    ("write_and_quit", functions::write_and_quit),
+    ("write_and_quit_all", functions::write_and_quit_all),
+    ("show_config", functions::show_config),
+    ("show_marks", functions::show_marks),
+    ("set", functions::set),
+    ("set_local", functions::set_local),
+    ("set_global", functions::set_global),
+    ("map", functions::map),
+    ("normal_map", functions::map),
+    ("insert_map", functions::insert_map),
+    ("unmap", functions::unmap),
+    ("normal_unmap", functions::unmap),
+    ("insert_unmap", functions::insert_unmap),
+];
+
+const RANGE_COMMANDS: &[(&str, RangeCommandFn)] = &[("read", functions::read)];
+
+// user commands running user commands stop after this many levels
+const MAX_COMMAND_DEPTH: usize = 20;
+
+// a command name maps to one alias or a list of them
+fn aliases(value: &Value) -> Vec<&str> {
+    match value {
+        Value::String(alias) => vec![alias.as_str()],
+        Value::Array(aliases) => aliases.iter().filter_map(|alias| alias.as_str()).collect(),
+        _ => vec![],
+    }
+}
+
+// aliases from `command_mode` with the functions of `table` their keys name
+fn registered<'a, T: Copy>(config: &'a Value, table: &[(&str, T)]) -> Vec<(&'a str, T)> {
+    let mut prefix_with_function_list: Vec<(&str, T)> = vec![];
 
     if let Value::Object(commands) = &config["command_mode"] {
         for (key, value) in commands.iter() {
-            match key.as_str() {
-                "edit_file" => match value.as_str() {
-                    Some(value) => prefix_with_function_list.push((value, functions::edit_file)),
-                    None => {}
-                },
-                "quit" => match value.as_str() {
-                    Some(value) => prefix_with_function_list.push((value, functions::quit)),
-                    None => {}
-                },
-                "quit_all" => match value.as_str() {
-                    Some(value) => prefix_with_function_list.push((value, functions::quit_all)),
-                    None => {}
-                },
-                "new_tab" => match value.as_str() {
-                    Some(value) => prefix_with_function_list.push((value, functions::new_tab)),
-                    None => {}
-                },
-                "write" => match value.as_str() {
-                    Some(value) => prefix_with_function_list.push((value, functions::write)),
-                    None => {}
-                },
-                "force_quit" => match value.as_str() {
-                    Some(value) => prefix_with_function_list.push((value, functions::force_quit)),
-                    None => {}
-                },
-                "force_quit_all" => match value.as_str() {
-                    Some(value) => {
-                        prefix_with_function_list.push((value, functions::force_quit_all))
-                    }
-                    None => {}
-                },
-                "write_all" => match value.as_str() {
-                    Some(value) => prefix_with_function_list.push((value, functions::write_all)),
-                    None => {}
-                },
-                "write_and_quit" => match value.as_str() {
-                    Some(value) => {
-                        prefix_with_function_list.push((value, functions::write_and_quit))
-                    }
-                    None => {}
-                },
-                "write_and_quit_all" => match value.as_str() {
-                    Some(value) => {
-                        prefix_with_function_list.push((value, functions::write_and_quit_all))
-                    }
-                    None => {}
-                },
-                key => {
-                    kass.set_error(format!("{} in the config doesn't exist", key).as_str());
+            if let Some((_, func)) = table.iter().find(|(name, _)| name == key) {
+                for alias in aliases(value) {
+                    prefix_with_function_list.push((alias, *func));
                 }
             }
         }
-    } else {
-        kass.set_error("Commands not found in the config");
     }
 
-    match kass.key_event.code {
-        event::KeyCode::Char(ch) => match ch {
-            _ => kass.app.command.push(ch),
-        },
-        KeyCode::Esc => {
-            kass.app.mode = Mode::Normal;
-            kass.app.command = String::new();
-        }
-        KeyCode::Enter => {
-            let command = &String::from(kass.app.command.clone())[1..];
-            let mut separated = command.splitn(2, ' ');
+    prefix_with_function_list
+}
+
+fn command_list(config: &Value) -> Vec<(&str, CommandFn)> {
+    registered(config, COMMANDS)
+}
 
-            if let Some(prefix) = separated.next() {
-                let mut is_num = false;
+fn range_command_list(config: &Value) -> Vec<(&str, RangeCommandFn)> {
+    registered(config, RANGE_COMMANDS)
+}
 
-                let mut line_number = String::new();
+// config key of the command an alias runs, e.g. `e` -> `edit_file`
+pub fn command_key<'a>(config: &'a Value, alias: &str) -> Option<&'a str> {
+    match &config["command_mode"] {
+        Value::Object(commands) => commands
+            .iter()
+            .find(|(_, value)| aliases(value).contains(&alias))
+            .map(|(key, _)| key.as_str()),
+        _ => None,
+    }
+}
 
-                for ch in prefix.chars() {
-                    if ch.is_digit(10) {
-                        line_number.push(ch);
-                        is_num = true;
-                    } else {
-                        is_num = false;
-                    }
-                }
+// every name that can be typed as a command, builtin or user-defined
+pub fn command_names(config: &Value) -> Vec<String> {
+    let mut names: Vec<String> = command_list(config)
+        .iter()
+        .map(|(alias, _)| alias.to_string())
+        .chain(
+            range_command_list(config)
+                .iter()
+                .map(|(alias, _)| alias.to_string()),
+        )
+        .collect();
+
+    if let Value::Object(commands) = &config["user_commands"] {
+        names.extend(commands.keys().cloned());
+    }
+
+    names
+}
 
-                if is_num {
-                    goto_line(kass, line_number.parse().expect("failed to parse"));
-                } else {
-                    let rest = separated.next().unwrap_or("");
-                    match prefix_with_function_list
-                        .iter_mut()
-                        .find(|(p, _)| *p == prefix)
-                    {
-                        Some((_, func)) => {
-                            func(rest, close, kass);
-                        }
-                        None => kass.set_error("Command not found."),
-                    }
+// problems with `command_mode` and `user_commands`, reported once when the config is loaded
+pub fn validate_commands(config: &Value) -> Vec<String> {
+    let mut errors = vec![];
+
+    match &config["command_mode"] {
+        Value::Object(commands) => {
+            for (key, value) in commands.iter() {
+                let known = COMMANDS.iter().any(|(name, _)| name == key)
+                    || RANGE_COMMANDS.iter().any(|(name, _)| name == key);
+
+                if !known {
+                    errors.push(format!("{} in the config doesn't exist", key));
+                } else if aliases(value).is_empty() {
+                    errors.push(format!("Provide a name or a list of names for {}", key));
                 }
             }
+        }
+        _ => errors.push(String::from("Commands not found in the config")),
+    }
 
-            kass.app.mode = Mode::Normal;
-            kass.app.command = String::new();
+    if let Value::Object(commands) = &config["user_commands"] {
+        for (name, value) in commands.iter() {
+            if user_command(value).is_none() {
+                errors.push(format!("User command {} must be a string or a list", name));
+            }
         }
-        KeyCode::Backspace => {
-            if kass.app.command.len() != 0 {
-                kass.app.command.pop();
+    }
+
+    errors
+}
+
+// `"w | e"` or `["w", "e"]`, each string of a list is a command line of its own
+fn user_command(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::String(command) => Some(vec![command.clone()]),
+        Value::Array(commands) => commands
+            .iter()
+            .map(|command| command.as_str().map(String::from))
+            .collect(),
+        _ => None,
+    }
+}
+
+// splits at `|`, which `\|` escapes, except that a shell command keeps the rest of the line
+fn split_commands(line: &str, config: &Value) -> Vec<String> {
+    let mut commands = vec![String::new()];
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '\\' if chars.peek().is_some_and(|(_, next)| *next == '|') => {
+                chars.next();
+                commands.last_mut().unwrap().push('|');
+            }
+            '|' if is_shell_command(commands.last().unwrap(), config) => {
+                commands.last_mut().unwrap().push_str(&line[i..]);
+                break;
+            }
+            '|' => commands.push(String::new()),
+            ch => commands.last_mut().unwrap().push(ch),
+        }
+    }
+
+    commands
+}
+
+// `!cmd`, `{range}!cmd`, `r !cmd` and `w !cmd`
+fn is_shell_command(command: &str, config: &Value) -> bool {
+    let (_, command) = range::parse(command.trim_start(), 1, 1);
+    let command = command.trim_start();
+
+    if command.starts_with('!') {
+        return true;
+    }
+
+    let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+    rest.trim_start().starts_with('!')
+        && matches!(command_key(config, name), Some("read" | "write"))
+}
+
+// runs a command line without its leading `:`
+pub fn execute_command(kass: &mut Kass, command: &str, close: &mut bool, config: &Value) {
+    let commands = split_commands(command, config)
+        .into_iter()
+        .map(|command| (command, 0))
+        .collect();
+    run_commands(kass, commands, close, config);
+}
+
+// chained commands stop at the first one that fails or asks a question, the ones after a
+// shell command wait for it in `after_shell`
+fn run_commands(kass: &mut Kass, commands: Vec<(String, usize)>, close: &mut bool, config: &Value) {
+    let mut commands = commands.into_iter();
+
+    while let Some((command, depth)) = commands.next() {
+        kass.app.action = CommandAction::Command;
+        execute_single(kass, command.trim(), close, config, depth);
+
+        if *close || kass.app.prompt.is_some() || matches!(kass.app.action, CommandAction::Error) {
+            break;
+        }
+
+        if kass.app.shell_command.is_some() {
+            kass.app.after_shell.extend(commands);
+            break;
+        }
+    }
+}
+
+// the rest of a command line once the main loop has run its shell command
+pub fn resume_commands(kass: &mut Kass, close: &mut bool, config: &Value) {
+    let commands = std::mem::take(&mut kass.app.after_shell);
+    run_commands(kass, commands, close, config);
+}
+
+// `[range]command args`, a range alone goes to its last line
+fn execute_single(kass: &mut Kass, command: &str, close: &mut bool, config: &Value, depth: usize) {
+    let tab = &kass.app.tabs[kass.app.active_index];
+    let current = (tab.cursor.y + tab.rowoff) as usize + 1;
+    let last = tab.rows.len();
+
+    let (range, command) = range::parse(command, current, last);
+    let command = command.trim_start();
+
+    if command.is_empty() {
+        if let Some(range) = range {
+            goto_line(kass, range.end);
+        }
+        return;
+    }
+
+    if range.is_some_and(|range| range.end > last) {
+        kass.set_error("Invalid range");
+        return;
+    }
+
+    // `:!cmd` runs a command, `:{range}!cmd` filters lines through it
+    if let Some(shell_command) = command.strip_prefix('!') {
+        match range {
+            Some(range) => functions::filter(range, shell_command.trim(), kass),
+            None => functions::run_shell(shell_command.trim(), kass),
+        }
+        return;
+    }
+
+    let (prefix, rest) = command.split_once(' ').unwrap_or((command, ""));
+
+    if let Some((_, func)) = range_command_list(config)
+        .iter()
+        .find(|(p, _)| *p == prefix)
+    {
+        func(range.unwrap_or(LineRange::line(current)), rest, close, kass);
+    } else if range.is_some() {
+        kass.set_error(format!("{} doesn't take a range", prefix).as_str());
+    } else {
+        match command_list(config).iter().find(|(p, _)| *p == prefix) {
+            Some((_, func)) => {
+                func(rest, close, kass);
+            }
+            None => match user_command(&config["user_commands"][prefix]) {
+                Some(_) if depth >= MAX_COMMAND_DEPTH => {
+                    kass.set_error(format!("{} calls itself too deeply", prefix).as_str())
+                }
+                Some(lines) => {
+                    let commands = lines
+                        .iter()
+                        .flat_map(|line| split_commands(&line.replace("<args>", rest), config))
+                        .map(|command| (command, depth + 1))
+                        .collect();
+                    run_commands(kass, commands, close, config);
+                }
+                None => kass.set_error("Command not found."),
+            },
+        }
+    }
+}
+
+pub fn handle_command_mode(kass: &mut Kass, close: &mut bool, config: &Value) -> Result<()> {
+    let ctrl = kass.key_event.modifiers.contains(KeyModifiers::CONTROL);
+    let cursor = kass.app.command_cursor;
+
+    // any other key keeps the completed text
+    if !matches!(kass.key_event.code, KeyCode::Tab | KeyCode::BackTab) {
+        kass.app.completion = None;
+    }
+
+    match kass.key_event.code {
+        KeyCode::Tab => complete(kass, config, true),
+        KeyCode::BackTab => complete(kass, config, false),
+        KeyCode::Char('w') if ctrl => {
+            let start = word_start(&kass.app.command, cursor);
+            edit_command(kass, start..cursor, "");
+        }
+        KeyCode::Char('u') if ctrl => edit_command(kass, 1..cursor, ""),
+        KeyCode::Char(_) if ctrl => {}
+        KeyCode::Char(ch) => edit_command(kass, cursor..cursor, ch.to_string().as_str()),
+        KeyCode::Esc => leave_command_mode(kass),
+        KeyCode::Enter => {
+            let command = kass.app.command.clone();
+            let limit = kass.option_number("history");
+            leave_command_mode(kass);
+
+            if let Err(e) = kass.history.add(&command[1..], limit) {
+                kass.set_error(e.to_string().as_str());
+            }
+            execute_command(kass, &command[1..], close, config);
+        }
+        // backspace on an empty command line leaves it like Esc
+        KeyCode::Backspace if kass.app.command == ":" => leave_command_mode(kass),
+        KeyCode::Backspace if cursor > 1 => {
+            let start = previous_boundary(&kass.app.command, cursor);
+            edit_command(kass, start..cursor, "");
+        }
+        KeyCode::Delete if cursor < kass.app.command.len() => {
+            let end = next_boundary(&kass.app.command, cursor);
+            edit_command(kass, cursor..end, "");
+        }
+        KeyCode::Left if cursor > 1 => {
+            kass.app.command_cursor = previous_boundary(&kass.app.command, cursor)
+        }
+        KeyCode::Right if cursor < kass.app.command.len() => {
+            kass.app.command_cursor = next_boundary(&kass.app.command, cursor)
+        }
+        KeyCode::Home => kass.app.command_cursor = 1,
+        KeyCode::End => kass.app.command_cursor = kass.app.command.len(),
+        KeyCode::Up => {
+            let typed = kass.app.command[1..].to_string();
+            if let Some(entry) = kass.history.older(typed.as_str()) {
+                let entry = entry.to_string();
+                show_history_entry(kass, entry);
+            }
+        }
+        KeyCode::Down => {
+            if let Some(entry) = kass.history.newer() {
+                let entry = entry.to_string();
+                show_history_entry(kass, entry);
             }
         }
         _ => {}
@@ -122,3 +354,85 @@ pub fn handle_command_mode(kass: &mut Kass, close: &mut bool, config: &Value) ->
 
     Ok(())
 }
+
+// puts the next candidate in place of the word before the cursor
+fn complete(kass: &mut Kass, config: &Value, forward: bool) {
+    if kass.app.completion.is_none() {
+        let line = &kass.app.command[1..kass.app.command_cursor];
+        let completion = Completion::new(line, config, &kass.app.tabs);
+
+        if completion.candidates.is_empty() {
+            return;
+        }
+        kass.app.completion = Some(completion);
+    }
+
+    if let Some(completion) = &mut kass.app.completion {
+        let start = completion.start + 1;
+        let text = completion.cycle(forward).to_string();
+
+        // a single match is taken as is, so the next Tab completes from it (e.g. into a directory)
+        if completion.candidates.len() == 1 {
+            kass.app.completion = None;
+        }
+
+        kass.app
+            .command
+            .replace_range(start..kass.app.command_cursor, &text);
+        kass.app.command_cursor = start + text.len();
+    }
+}
+
+fn leave_command_mode(kass: &mut Kass) {
+    kass.app.mode = Mode::Normal;
+    kass.app.command = String::new();
+    kass.app.command_cursor = 0;
+    kass.history.reset();
+}
+
+// typing stops going through the history
+fn edit_command(kass: &mut Kass, range: Range<usize>, text: &str) {
+    kass.app.command.replace_range(range.clone(), text);
+    kass.app.command_cursor = range.start + text.len();
+    kass.history.reset();
+}
+
+fn show_history_entry(kass: &mut Kass, entry: String) {
+    kass.app.command = format!(":{}", entry);
+    kass.app.command_cursor = kass.app.command.len();
+}
+
+fn previous_boundary(text: &str, index: usize) -> usize {
+    text[..index]
+        .char_indices()
+        .next_back()
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+fn next_boundary(text: &str, index: usize) -> usize {
+    text[index..]
+        .chars()
+        .next()
+        .map(|ch| index + ch.len_utf8())
+        .unwrap_or(index)
+}
+
+// start of the word Ctrl-w deletes: spaces before the cursor, then a run of word or other characters
+fn word_start(text: &str, end: usize) -> usize {
+    let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let before = text[..end].trim_end();
+
+    let start = match before.chars().next_back() {
+        Some(last) => before
+            .char_indices()
+            .rev()
+            .find(|(_, ch)| ch.is_whitespace() || is_word(*ch) != is_word(last))
+            .map(|(i, ch)| i + ch.len_utf8())
+            .unwrap_or(0),
+        None => 0,
+    };
+
+    // the leading `:` stays
+    start.max(1)
+}
@@ -1,37 +1,179 @@
 use std::io::Result;
 
-use crossterm::event;
+use crossterm::event::{KeyCode, KeyModifiers};
 
-use crate::{enums::Mode, kass::Kass};
+use crate::{
+    enums::Mode,
+    kass::Kass,
+    keymap::{Key, Lookup},
+    macros::{is_register, typed_text},
+    mode_handlers::normal::finish_change,
+};
 
 pub fn handle_insert_mode(kass: &mut Kass) -> Result<()> {
-    let curr_row = kass.cursor.y as usize + kass.app.tabs[kass.app.active_index].rowoff as usize;
-    match kass.key_event.code {
-        event::KeyCode::Char(c) => {
-            if kass.cursor.x as usize == kass.app.tabs[kass.app.active_index].rows[curr_row].len()
-                || kass.app.tabs[kass.app.active_index].rows[curr_row].len() == 0
-            {
-                kass.app.tabs[kass.app.active_index].rows[curr_row].push(c);
-            } else {
-                kass.app.tabs[kass.app.active_index].rows[curr_row]
-                    .insert(kass.cursor.x as usize, c);
+    insert_mode_key(kass, Key::from_event(&kass.key_event))
+}
+
+pub fn insert_mode_key(kass: &mut Kass, key: Key) -> Result<()> {
+    if let Some(change) = kass.recording.as_mut() {
+        change.typed.push(key);
+    }
+
+    // the register after `Ctrl-r`, any other key cancels
+    if kass.awaiting_char.take().is_some() {
+        if let KeyCode::Char(ch) = key.code {
+            insert_register(kass, ch);
+        }
+        return Ok(());
+    }
+
+    kass.pending_keys.push(key);
+
+    // keys that turn out not to be a mapping are typed as they are
+    while !kass.pending_keys.is_empty() {
+        match kass.keymap.lookup(Mode::Insert, &kass.pending_keys) {
+            Lookup::Action(action) => {
+                kass.pending_keys.clear();
+                run_action(kass, action.as_str())?;
+            }
+            Lookup::Pending(_) => break,
+            Lookup::None => {
+                let key = kass.pending_keys.remove(0);
+                insert_key(kass, key);
             }
+        }
+    }
 
-            kass.app.tabs[kass.app.active_index].cursor.x += 1;
+    finish_change(kass);
+    Ok(())
+}
+
+// no key followed a sequence that starts a longer mapping
+pub fn insert_mode_timeout(kass: &mut Kass) -> Result<()> {
+    let keys: Vec<Key> = kass.pending_keys.drain(..).collect();
+
+    match kass.keymap.lookup(Mode::Insert, &keys) {
+        Lookup::Pending(Some(action)) => run_action(kass, action.as_str())?,
+        _ => {
+            for key in keys {
+                insert_key(kass, key);
+            }
         }
-        event::KeyCode::Backspace => {
-            kass.app.tabs[kass.app.active_index].delete();
+    }
+
+    finish_change(kass);
+    Ok(())
+}
+
+fn insert_key(kass: &mut Kass, key: Key) {
+    let c = match key.code {
+        KeyCode::Char(c) if (key.modifiers - KeyModifiers::SHIFT).is_empty() => c,
+        _ => return,
+    };
+    if kass.app.mode == Mode::Replace {
+        let original = kass.app.tabs[kass.app.active_index].overwrite_char(c);
+        kass.replaced.push(original);
+        return;
+    }
+
+    let indent = kass.indent();
+    let pairs = kass.auto_pairs();
+
+    kass.app.tabs[kass.app.active_index].at_cursors(|tab| {
+        if !tab.type_pair(c, &pairs) {
+            tab.type_char(c, &indent);
+        }
+    });
+}
+
+// `Ctrl-r` with `"` types the register `p` puts, with a letter the text of that macro
+fn insert_register(kass: &mut Kass, name: char) {
+    let text = match name {
+        '"' => Some(kass.register.as_text()),
+        _ if is_register(name) => kass
+            .macros
+            .get(&name.to_ascii_lowercase())
+            .map(|keys| typed_text(keys)),
+        _ => None,
+    };
+
+    match text {
+        Some(text) => kass.app.tabs[kass.app.active_index].insert_text(&text),
+        None => kass.set_error(format!("Nothing in register {}", name).as_str()),
+    }
+}
+
+fn run_action(kass: &mut Kass, action: &str) -> Result<()> {
+    // Backspace in replace mode only gives back what was typed over since the cursor last moved
+    if action != "backspace" {
+        kass.replaced.clear();
+    }
+
+    match action {
+        "backspace" if kass.app.mode == Mode::Replace => {
+            let tab = &mut kass.app.tabs[kass.app.active_index];
+
+            match kass.replaced.pop() {
+                Some(original) => tab.restore_char(original),
+                None => tab.insert_move(false),
+            }
+        }
+        "backspace" => {
+            let (softtabstop, tabstop) = (kass.softtabstop(), kass.option_number("tabstop"));
+            let pairs = kass.auto_pairs();
+
+            kass.app.tabs[kass.app.active_index].at_cursors(|tab| {
+                if !tab.delete_pair(&pairs) && !tab.delete_soft_tab(softtabstop, tabstop) {
+                    tab.delete();
+                }
+            });
         }
-        event::KeyCode::Enter => {
-            kass.app.tabs[kass.app.active_index].goto_newline()?;
+        "newline" => {
+            let indent = kass.indent();
+            let mut result = Ok(());
+
+            kass.app.tabs[kass.app.active_index].at_cursors(|tab| {
+                if result.is_ok() {
+                    result = tab.goto_newline(&indent);
+                }
+            });
+            result?;
+        }
+        "tab" => {
+            let (expandtab, softtabstop, tabstop) = (
+                kass.option_bool("expandtab"),
+                kass.softtabstop(),
+                kass.option_number("tabstop"),
+            );
+            kass.app.tabs[kass.app.active_index]
+                .at_cursors(|tab| tab.insert_tab(expandtab, softtabstop, tabstop));
         }
-        event::KeyCode::Tab => {
-            let spaces: String = (0..4).map(|_| ' ').collect();
-            kass.app.tabs[kass.app.active_index].rows[curr_row]
-                .insert_str(kass.cursor.x as usize, &spaces);
-            kass.app.tabs[kass.app.active_index].cursor.x += 4;
+        "normal" => {
+            kass.app.mode = Mode::Normal;
+        }
+        "left" | "right" => kass.app.tabs[kass.app.active_index].insert_move(action == "right"),
+        "up" | "down" => {
+            kass.app.tabs[kass.app.active_index].insert_move_vertical(action == "down")
+        }
+        "line_start" => kass.app.tabs[kass.app.active_index].cursor.x = 0,
+        "line_end" => {
+            let tab = &mut kass.app.tabs[kass.app.active_index];
+            tab.cursor.x = tab.rows[(tab.cursor.y + tab.rowoff) as usize].len() as u16;
+        }
+        // these only edit at the cursor, so the other cursors go
+        "delete_char" | "delete_word" | "delete_to_line_start" | "insert_register" => {
+            let tab = &mut kass.app.tabs[kass.app.active_index];
+            tab.extra_cursors.clear();
+
+            match action {
+                "delete_char" => tab.delete_forward(),
+                "delete_word" => tab.delete_word_before(),
+                "delete_to_line_start" => tab.delete_to_line_start(),
+                _ => kass.awaiting_char = Some((action.to_string(), 1)),
+            }
         }
-        event::KeyCode::Esc => {
+        "normal_command" => {
+            kass.return_to = Some(kass.app.mode);
             kass.app.mode = Mode::Normal;
         }
         _ => {}
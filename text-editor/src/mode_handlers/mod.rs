@@ -1,3 +1,4 @@
 pub mod command;
 pub mod insert;
 pub mod normal;
+pub mod prompt;
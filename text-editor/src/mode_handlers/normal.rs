@@ -1,175 +1,624 @@
 use crate::{
-    enums::{Action, Element, Mode},
+    change::{Change, CHANGE_ACTIONS},
+    editor::Editor,
+    enums::{BufferKind, Mode, ScreenRow},
     kass::Kass,
+    keymap::{Key, Lookup},
+    macros::{is_register, MacroRecording, MAX_MACRO_DEPTH},
+    marks,
+    mode_handlers::{
+        command::execute_command,
+        insert::{insert_mode_key, insert_mode_timeout},
+    },
+    register::Register,
+    text_object,
 };
-use crossterm::event::{self, KeyCode, KeyEvent};
+use crossterm::event::KeyCode;
+use serde_json::Value;
 use std::io::Result;
 
-fn add_to_buf(kass: &mut Kass) {
-    match kass.key_event {
-        KeyEvent {
-            code: KeyCode::Char(c),
-            ..
-        } => kass.buf.push(c),
-        _ => {}
+pub fn handle_normal_mode(kass: &mut Kass, close: &mut bool, config: &Value) -> Result<()> {
+    let key = Key::from_event(&kass.key_event);
+    normal_key(kass, key, close, config);
+
+    Ok(())
+}
+
+fn normal_key(kass: &mut Kass, key: Key, close: &mut bool, config: &Value) {
+    // the character for `r` and the like, any other key cancels
+    if let Some((action, count)) = kass.awaiting_char.take() {
+        match key.code {
+            KeyCode::Char(ch) => run_char_action(kass, action.as_str(), ch, count),
+            _ => kass.recording = None,
+        }
+        return;
+    }
+
+    // a count before the keys, `0` only continues one
+    if kass.pending_keys.is_empty() && key.modifiers.is_empty() {
+        if let KeyCode::Char(ch @ '0'..='9') = key.code {
+            if ch != '0' || !kass.buf.is_empty() {
+                kass.buf.push(ch);
+                return;
+            }
+        }
+    }
+
+    kass.pending_keys.push(key);
+
+    match kass.keymap.lookup(Mode::Normal, &kass.pending_keys) {
+        Lookup::Action(action) => run_action(kass, action.as_str(), close, config),
+        Lookup::Pending(_) => {}
+        Lookup::None => {
+            // `d` then `i`: the shorter mapping runs and the last key starts over
+            let last = kass.pending_keys.pop();
+
+            match (kass.keymap.lookup(Mode::Normal, &kass.pending_keys), last) {
+                (Lookup::Pending(Some(action)), Some(last)) => {
+                    run_action(kass, action.as_str(), close, config);
+                    normal_key(kass, last, close, config);
+                }
+                _ => {
+                    kass.pending_keys.clear();
+                    kass.buf.clear();
+                }
+            }
+        }
     }
 }
 
-fn parse_buf(input: String) -> Vec<Element> {
-    // Parse elements into a list
-    let mut elements_list: Vec<Element> = Vec::new();
-    let mut current_number = String::new();
+// no key followed a sequence that starts a longer mapping
+pub fn normal_mode_timeout(kass: &mut Kass, close: &mut bool, config: &Value) {
+    match kass.keymap.lookup(Mode::Normal, &kass.pending_keys) {
+        Lookup::Pending(Some(action)) => run_action(kass, action.as_str(), close, config),
+        _ => {
+            kass.pending_keys.clear();
+            kass.buf.clear();
+        }
+    }
+}
+
+fn run_char_action(kass: &mut Kass, action: &str, ch: char, count: usize) {
+    if let Some(change) = kass.recording.as_mut() {
+        change.chars.push(ch);
+    }
 
-    for ch in input.chars() {
-        if ch.is_digit(10) {
-            current_number.push(ch);
-        } else {
-            if !current_number.is_empty() {
-                let number = current_number.parse().unwrap();
-                elements_list.push(Element::Num(number));
-                current_number.clear();
+    match (action, ch) {
+        ("replace_char", _) => kass.app.tabs[kass.app.active_index].replace_chars(ch, count),
+        // `iw`, `a(` and the others pick the text object after an operator
+        ("delete" | "change" | "yank", 'i' | 'a') => {
+            let scope = if ch == 'i' { "inner" } else { "around" };
+            kass.awaiting_char = Some((format!("{}_{}", action, scope), count));
+        }
+        ("record_macro", _) if is_register(ch) => {
+            kass.macro_recording = Some(MacroRecording::new(ch));
+        }
+        ("play_macro", _) if ch == '@' || is_register(ch) => play_macro(kass, ch, count),
+        ("set_mark", _) => set_mark(kass, ch),
+        ("goto_mark" | "goto_mark_line", _) => goto_mark(kass, ch, action == "goto_mark_line"),
+        // `dd`, `cc` and `yy` work on whole lines
+        ("delete", 'd') => {
+            let lines = kass.app.tabs[kass.app.active_index].delete_lines(count);
+            kass.register = Register::lines(lines);
+        }
+        ("yank", 'y') => {
+            let lines = kass.app.tabs[kass.app.active_index].yank_lines(count);
+            kass.register = Register::lines(lines);
+        }
+        ("change", 'c') => {
+            let autoindent = kass.option_bool("autoindent");
+            let lines = kass.app.tabs[kass.app.active_index].change_lines(count, autoindent);
+            kass.register = Register::lines(lines);
+            kass.app.mode = Mode::Insert;
+        }
+        _ => {
+            if let Some((operator @ ("delete" | "change" | "yank"), scope)) = action.split_once('_')
+            {
+                run_operator(kass, operator, ch, scope == "around");
             }
-            elements_list.push(Element::Char(ch));
         }
     }
 
-    if !current_number.is_empty() {
-        let number = current_number.parse().unwrap();
-        elements_list.push(Element::Num(number));
+    keep_cursors_for(kass, action);
+    finish_change(kass);
+}
+
+// the extra cursors go along into insert mode with `i` and `a`, which leave them where they
+// do the cursor, and come from a block with `I` and `A`; a block ends with a change or a mode
+fn keep_cursors_for(kass: &mut Kass, action: &str) {
+    let insert = matches!(kass.app.mode, Mode::Insert | Mode::Replace);
+    let changed = CHANGE_ACTIONS.contains(&action)
+        || matches!(action.split_once('_'), Some(("delete" | "change", _)));
+    let tab = &mut kass.app.tabs[kass.app.active_index];
+
+    // a change in normal mode only happens at the cursor and would leave the others on
+    // columns of text that moved
+    if (insert
+        && !matches!(
+            action,
+            "insert" | "append" | "block_insert" | "block_append"
+        ))
+        || (!insert && changed)
+    {
+        tab.extra_cursors.clear();
     }
 
-    elements_list
+    if kass.app.mode != Mode::Normal || CHANGE_ACTIONS.contains(&action) {
+        tab.block_start = None;
+    }
 }
 
-pub fn handle_normal_mode(kass: &mut Kass) -> Result<()> {
-    add_to_buf(kass);
-    let parsed_buf = parse_buf(kass.buf.clone());
-    let mut _action = Action::Default;
+// an operator on the text object named by `name` around the cursor, nothing happens without one
+fn run_operator(kass: &mut Kass, operator: &str, name: char, around: bool) {
+    let tab = &mut kass.app.tabs[kass.app.active_index];
+    let (text, cursor) = tab.text_with_cursor();
 
-    if !parsed_buf.is_empty() {
-        match parsed_buf[0] {
-            Element::Char(ch) => match ch {
-                'd' => _action = Action::Delete,
-                _ => {}
-            },
-            _ => {}
-        }
-
-        match parsed_buf[parsed_buf.len() - 1] {
-            Element::Char(c) => match c {
-                'i' => insert_i(kass),
-                'a' => insert_a(kass),
-                ':' => go_to_command(kass),
-                'h' => {
-                    let mut count = 1;
-                    if parsed_buf.len() >= 2 {
-                        count = match parsed_buf[parsed_buf.len() - 2] {
-                            Element::Num(num) => num,
-                            _ => 1,
-                        };
-                    }
-                    for _ in 0..count {
-                        nav_h(kass)
-                    }
+    let object = match text_object::find(&text, cursor, name, around) {
+        Some(object) => object,
+        None => return,
+    };
+
+    kass.register = match operator {
+        "delete" => tab.take_object(&object, true),
+        "change" => {
+            kass.app.mode = Mode::Insert;
+            tab.change_object(&object)
+        }
+        "yank" => tab.take_object(&object, false),
+        _ => return,
+    };
+}
+
+// `q` again, the keys typed since `q{reg}` go in the register
+fn stop_macro(kass: &mut Kass) {
+    let mut recording = match kass.macro_recording.take() {
+        Some(recording) => recording,
+        None => return,
+    };
+    recording.keys.truncate(recording.sequence_start);
+
+    let register = recording.register.to_ascii_lowercase();
+    if recording.register.is_ascii_uppercase() {
+        kass.macros
+            .entry(register)
+            .or_default()
+            .extend(recording.keys);
+    } else {
+        kass.macros.insert(register, recording.keys);
+    }
+}
+
+// `@{reg}` queues the register's keys `count` times ahead of anything still queued, `@@` the last one
+fn play_macro(kass: &mut Kass, ch: char, count: usize) {
+    let register = match ch {
+        '@' => kass.last_macro,
+        _ => Some(ch.to_ascii_lowercase()),
+    };
+    let keys = match register.and_then(|register| kass.macros.get(&register)) {
+        Some(keys) => keys.clone(),
+        None => {
+            kass.set_error("No macro recorded");
+            return;
+        }
+    };
+
+    if kass.replay_depth >= MAX_MACRO_DEPTH {
+        kass.set_error("Macros nested too deep");
+        kass.replay.clear();
+        return;
+    }
+
+    kass.last_macro = register;
+    let depth = kass.replay_depth + 1;
+
+    for _ in 0..count {
+        for key in keys.iter().rev() {
+            kass.replay.push_front((*key, depth));
+        }
+    }
+}
+
+fn screen_row(action: &str) -> ScreenRow {
+    if action.ends_with("top") {
+        ScreenRow::Top
+    } else if action.ends_with("middle") {
+        ScreenRow::Middle
+    } else {
+        ScreenRow::Bottom
+    }
+}
+
+// `m{a-z}` marks the cursor position in the buffer, `m{A-Z}` in the file
+fn set_mark(kass: &mut Kass, name: char) {
+    let tab = &mut kass.app.tabs[kass.app.active_index];
+    let here = tab.mark_here();
+
+    if marks::is_local(name) {
+        tab.marks.insert(name, here);
+    } else if marks::is_file_mark(name) {
+        if tab.kind != BufferKind::File {
+            kass.set_error("File marks need a file");
+            return;
+        }
+
+        let filepath = tab.filepath.clone();
+        for tab in kass.app.tabs.iter_mut() {
+            tab.marks.remove(&name);
+        }
+
+        kass.app.tabs[kass.app.active_index]
+            .marks
+            .insert(name, here);
+        kass.file_marks.insert(name, (filepath, here));
+    }
+}
+
+// `` `a `` and `'a`, a file mark switches to its file's tab or opens one
+fn goto_mark(kass: &mut Kass, name: char, linewise: bool) {
+    // `` ` `` and `''` both go back to before the last jump
+    let name = if name == '`' { '\'' } else { name };
+
+    if marks::is_file_mark(name) {
+        let (filepath, mark) = match kass.file_marks.get(&name) {
+            Some(file_mark) => file_mark.clone(),
+            None => {
+                kass.set_error("Mark not set");
+                return;
+            }
+        };
+
+        let open = kass
+            .app
+            .tabs
+            .iter()
+            .position(|tab| tab.kind == BufferKind::File && tab.filepath == filepath);
+
+        match open {
+            Some(index) => {
+                kass.app.active_index = index;
+                kass.app.tabs[index].marks.entry(name).or_insert(mark);
+            }
+            None => match Editor::new(filepath) {
+                Ok(mut tab) => {
+                    tab.editor_size = kass.app.tabs[kass.app.active_index].editor_size;
+                    tab.marks.insert(name, mark);
+                    kass.app.tabs.push(tab);
+                    kass.app.active_index = kass.app.tabs.len() - 1;
                 }
-                'j' => {
-                    let mut count = 1;
-                    if parsed_buf.len() >= 2 {
-                        count = match parsed_buf[parsed_buf.len() - 2] {
-                            Element::Num(num) => num,
-                            _ => 1,
-                        };
-                    }
-                    for _ in 0..count {
-                        nav_j(kass)
-                    }
+                Err(e) => {
+                    kass.set_error(e.to_string().as_str());
+                    return;
                 }
-                'k' => {
-                    let mut count = 1;
-                    if parsed_buf.len() >= 2 {
-                        count = match parsed_buf[parsed_buf.len() - 2] {
-                            Element::Num(num) => num,
-                            _ => 1,
-                        };
-                    }
-                    for _ in 0..count {
-                        nav_k(kass)
-                    }
+            },
+        }
+    }
+
+    let tab = &mut kass.app.tabs[kass.app.active_index];
+    match tab.marks.get(&name).copied() {
+        Some(mark) => {
+            tab.push_jump();
+            tab.goto_mark(mark, linewise);
+        }
+        None => kass.set_error("Mark not set"),
+    }
+}
+
+// a change that is done, no longer waiting for a character or in insert mode, is the one `.` repeats
+pub fn finish_change(kass: &mut Kass) {
+    if kass.awaiting_char.is_none() && kass.app.mode == Mode::Normal {
+        if let Some(change) = kass.recording.take() {
+            kass.last_change = Some(change);
+        }
+    }
+}
+
+// `.` runs the last change again through the same handlers, a count replaces the one it had
+fn repeat_change(kass: &mut Kass, count: Option<usize>, close: &mut bool, config: &Value) {
+    let change = match kass.last_change.clone() {
+        Some(change) => change,
+        None => return,
+    };
+
+    kass.buf = count.unwrap_or(change.count).to_string();
+    run_action(kass, change.action.as_str(), close, config);
+
+    for ch in change.chars {
+        if let Some((action, count)) = kass.awaiting_char.take() {
+            run_char_action(kass, action.as_str(), ch, count);
+        }
+    }
+
+    if matches!(kass.app.mode, Mode::Insert | Mode::Replace) {
+        for key in change.typed {
+            if insert_mode_key(kass, key).is_err() {
+                break;
+            }
+        }
+        if !kass.pending_keys.is_empty() {
+            let _ = insert_mode_timeout(kass);
+        }
+    }
+}
+
+fn run_action(kass: &mut Kass, action: &str, close: &mut bool, config: &Value) {
+    let counted = kass.buf.parse::<usize>().ok();
+    let count = counted.unwrap_or(1);
+    kass.buf.clear();
+    kass.pending_keys.clear();
+
+    if CHANGE_ACTIONS.contains(&action) {
+        kass.recording = Some(Change::new(action, count));
+    }
+
+    if let Some(command) = action.strip_prefix(':') {
+        execute_command(kass, command, close, config);
+        return;
+    }
+
+    match action {
+        "insert" => insert_i(kass),
+        "replace_mode" => {
+            kass.replaced.clear();
+            kass.app.mode = Mode::Replace;
+            kass.app.tabs[kass.app.active_index].move_left(1);
+        }
+        "append" => insert_a(kass),
+        "open_below" => open_line(kass, true),
+        "open_above" => open_line(kass, false),
+        "command_mode" => go_to_command(kass),
+        "left" => {
+            for _ in 0..count {
+                nav_h(kass)
+            }
+        }
+        "down" => {
+            for _ in 0..count {
+                nav_j(kass)
+            }
+        }
+        "up" => {
+            for _ in 0..count {
+                nav_k(kass)
+            }
+        }
+        "right" => {
+            for _ in 0..count {
+                nav_l(kass)
+            }
+        }
+        "next_tab" => kass.app.next(),
+        "prev_tab" => kass.app.previous(),
+        "command_history" => command_history(kass),
+        "delete_line" => {
+            let lines = kass.app.tabs[kass.app.active_index].delete_lines(count);
+            kass.register = Register::lines(lines);
+        }
+        "yank_line" => {
+            let lines = kass.app.tabs[kass.app.active_index].yank_lines(count);
+            kass.register = Register::lines(lines);
+        }
+        "join" => kass.app.tabs[kass.app.active_index].join_lines(count),
+        "undo" => {
+            let undone = kass.app.tabs[kass.app.active_index].undo();
+            if !undone {
+                kass.set_error("Nothing to undo (only filters, :r and cc)");
+            }
+        }
+        "delete_char" => {
+            let text = kass.app.tabs[kass.app.active_index].delete_chars(count);
+            if !text.is_empty() {
+                kass.register = Register::chars(text);
+            }
+        }
+        "repeat" => repeat_change(kass, counted, close, config),
+        "record_macro" if kass.macro_recording.is_some() => stop_macro(kass),
+        "add_cursor_next_match" => {
+            for _ in 0..count {
+                if !kass.app.tabs[kass.app.active_index].add_cursor_at_next_match() {
+                    kass.set_error("No other match of a word under the cursor");
+                    break;
                 }
-                'l' => {
-                    let mut count = 1;
-                    if parsed_buf.len() >= 2 {
-                        count = match parsed_buf[parsed_buf.len() - 2] {
-                            Element::Num(num) => num,
-                            _ => 1,
-                        };
-                    }
-                    for _ in 0..count {
-                        nav_l(kass)
-                    }
+            }
+        }
+        "add_cursor_below" | "add_cursor_above" => kass.app.tabs[kass.app.active_index]
+            .add_cursor_vertical(action == "add_cursor_below", count),
+        "clear" => {
+            let tab = &mut kass.app.tabs[kass.app.active_index];
+            tab.extra_cursors.clear();
+            tab.block_start = None;
+        }
+        "visual_block" => kass.app.tabs[kass.app.active_index].toggle_block(),
+        "block_insert" | "block_append" => block_insert(kass, action == "block_append"),
+        "matching_bracket" => match counted {
+            // `N%` goes to the line N percent of the way through the buffer
+            Some(percent) => {
+                let tab = &mut kass.app.tabs[kass.app.active_index];
+                let row = (percent.min(100) * tab.rows.len()).div_ceil(100).max(1) - 1;
+
+                tab.push_jump();
+                tab.goto_row(row);
+                tab.cursor_on_first_non_blank();
+            }
+            None => {
+                if !kass.app.tabs[kass.app.active_index].goto_matching_bracket() {
+                    kass.set_error("No matching bracket");
                 }
-                _ => {}
-            },
-            _ => {}
-        }
-    }
-
-    match kass.key_event {
-        KeyEvent {
-            code: event::KeyCode::Tab,
-            ..
-        } => next_tab(kass),
-        KeyEvent {
-            code: event::KeyCode::BackTab,
-            ..
-        } => prev_tab(kass),
-        KeyEvent {
-            code: KeyCode::Esc, ..
-        } => kass.buf = String::new(),
+            }
+        },
+        "jump_back" => kass.app.tabs[kass.app.active_index].jump_back(count),
+        "jump_forward" => kass.app.tabs[kass.app.active_index].jump_forward(count),
+        // `gg` and `G` go to the first or last line, or line N with a count
+        "first_line" | "last_line" => {
+            let tab = &mut kass.app.tabs[kass.app.active_index];
+            let row = match counted {
+                Some(line) => line - 1,
+                None if action == "first_line" => 0,
+                None => tab.rows.len() - 1,
+            };
+
+            tab.push_jump();
+            tab.goto_row(row);
+            tab.cursor_on_first_non_blank();
+        }
+        "page_down" | "page_up" => {
+            let scrolloff = kass.option_number("scrolloff");
+            kass.app.tabs[kass.app.active_index].scroll_page(
+                action == "page_down",
+                count,
+                scrolloff,
+            );
+        }
+        "half_page_down" | "half_page_up" => {
+            kass.app.tabs[kass.app.active_index].scroll_half(action == "half_page_down", counted)
+        }
+        "screen_top" | "screen_middle" | "screen_bottom" => {
+            let scrolloff = kass.option_number("scrolloff");
+            let place = screen_row(action);
+            kass.app.tabs[kass.app.active_index].goto_screen_row(place, count, scrolloff);
+        }
+        "scroll_top" | "scroll_middle" | "scroll_bottom" => {
+            let scrolloff = kass.option_number("scrolloff");
+            let place = screen_row(action);
+            kass.app.tabs[kass.app.active_index].align_view(place, scrolloff);
+        }
+        "line_start" | "line_end" => {
+            kass.app.tabs[kass.app.active_index].goto_line_edge(action == "line_end")
+        }
+        "replace_char" | "delete" | "change" | "yank" | "record_macro" | "play_macro"
+        | "set_mark" | "goto_mark" | "goto_mark_line" => {
+            kass.awaiting_char = Some((action.to_string(), count))
+        }
+        "toggle_case" => kass.app.tabs[kass.app.active_index].toggle_case(count),
+        "put_after" | "put_before" => {
+            let register = kass.register.clone();
+            for _ in 0..count {
+                kass.app.tabs[kass.app.active_index].put(&register, action == "put_after");
+            }
+        }
+        "enter" => match kass.app.tabs[kass.app.active_index].kind {
+            BufferKind::CommandHistory => run_history_line(kass, close, config),
+            BufferKind::File => {
+                for _ in 0..count {
+                    nav_j(kass)
+                }
+            }
+        },
         _ => {}
     }
 
+    keep_cursors_for(kass, action);
+    finish_change(kass);
+
     // functions
     fn insert_i(kass: &mut Kass) {
+        kass.app.tabs[kass.app.active_index].extra_cursors_before_char();
         kass.app.tabs[kass.app.active_index].move_left(1);
         kass.app.mode = Mode::Insert;
-        kass.buf.clear();
     }
     fn insert_a(kass: &mut Kass) {
         kass.app.mode = Mode::Insert;
-        kass.buf.clear();
+    }
+    // `I` and `A` without a block insert before the first non-blank or at the end of the line
+    fn block_insert(kass: &mut Kass, append: bool) {
+        let tabstop = kass.option_number("tabstop");
+        let tab = &mut kass.app.tabs[kass.app.active_index];
+
+        if !tab.block_to_cursors(append, tabstop) {
+            let row = &tab.rows[(tab.cursor.y + tab.rowoff) as usize];
+            let x = match append {
+                true => row.len(),
+                false => row.len() - row.trim_start().len(),
+            };
+
+            tab.extra_cursors.clear();
+            tab.cursor.x = x as u16;
+        }
+        kass.app.mode = Mode::Insert;
+    }
+    fn open_line(kass: &mut Kass, below: bool) {
+        let indent = kass.indent();
+        kass.app.tabs[kass.app.active_index].open_line(below, &indent);
+        kass.app.mode = Mode::Insert;
     }
     fn nav_l(kass: &mut Kass) {
         kass.app.tabs[kass.app.active_index].move_right(1);
-        kass.buf.clear();
     }
     fn nav_h(kass: &mut Kass) {
-        if kass.cursor.x != 1 {
+        if kass.app.tabs[kass.app.active_index].cursor.x != 1 {
             kass.app.tabs[kass.app.active_index].move_left(1);
         }
-        kass.buf.clear();
     }
     fn go_to_command(kass: &mut Kass) {
         kass.app.mode = Mode::Command;
         kass.app.command.push(':');
-        kass.buf.clear();
+        kass.app.command_cursor = 1;
+    }
+    // the history in a tab of its own, opened next to the current one on the newest entry
+    fn command_history(kass: &mut Kass) {
+        let mut window = Editor::scratch(
+            "[Command Line]",
+            kass.history.entries.clone(),
+            BufferKind::CommandHistory,
+        );
+        window.editor_size = kass.app.tabs[kass.app.active_index].editor_size;
+        for _ in 1..window.rows.len() {
+            window.move_down(1);
+        }
+
+        kass.app.active_index += 1;
+        kass.app.tabs.insert(kass.app.active_index, window);
+    }
+    // closes the window, unless it is the last tab, and runs the line under the cursor
+    fn run_history_line(kass: &mut Kass, close: &mut bool, config: &Value) {
+        let window = &kass.app.tabs[kass.app.active_index];
+        let line = window.rows[(window.cursor.y + window.rowoff) as usize].clone();
+
+        if kass.app.tabs.len() > 1 {
+            kass.app.tabs.remove(kass.app.active_index);
+            kass.app.active_index = kass.app.active_index.saturating_sub(1);
+        }
+
+        let limit = kass.option_number("history");
+
+        if let Err(e) = kass.history.add(line.as_str(), limit) {
+            kass.set_error(e.to_string().as_str());
+        }
+        execute_command(kass, line.as_str(), close, config);
     }
     fn nav_j(kass: &mut Kass) {
         kass.app.tabs[kass.app.active_index].move_down(1);
-        kass.buf.clear();
     }
     fn nav_k(kass: &mut Kass) {
         kass.app.tabs[kass.app.active_index].move_up(1);
-        kass.buf.clear();
     }
-    fn next_tab(kass: &mut Kass) {
-        kass.app.next();
-        kass.buf.clear();
-    }
-    fn prev_tab(kass: &mut Kass) {
-        kass.app.previous();
-        kass.buf.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::parse_keys;
+
+    fn feed(kass: &mut Kass, keys: &str) {
+        let mut close = false;
+        for key in parse_keys(keys, &[]).unwrap() {
+            normal_key(kass, key, &mut close, &Value::Null);
+        }
     }
 
-    Ok(())
+    #[test]
+    fn change_at_the_cursor_drops_extra_cursors() {
+        let mut kass = Kass::new().unwrap();
+        kass.app.tabs[0] = Editor::scratch("test", vec!["a éé éé".to_string()], BufferKind::File);
+
+        // on the first `éé`
+        kass.app.tabs[0].cursor.x = 4;
+        feed(&mut kass, "gb");
+        assert_eq!(kass.app.tabs[0].extra_cursors.len(), 1);
+
+        feed(&mut kass, "0x");
+        let tab = &kass.app.tabs[0];
+        assert_eq!(tab.rows, [" éé éé"]);
+        assert!(tab.extra_cursors.is_empty());
+        assert!(tab.extra_cursor_cells(true, 8).is_empty());
+    }
 }
@@ -0,0 +1,70 @@
+use std::io::Result;
+
+use crossterm::event::KeyCode;
+
+use crate::{
+    functions::{close_tab, confirm_quit_all, open_file, save_tab},
+    kass::Kass,
+    prompt::{Answer, PromptAction},
+};
+
+// keys other than the listed answers and Esc leave the question open
+pub fn handle_prompt(kass: &mut Kass, close: &mut bool) -> Result<()> {
+    let prompt = match kass.app.prompt.take() {
+        Some(prompt) => prompt,
+        None => return Ok(()),
+    };
+
+    let answer = match kass.key_event.code {
+        KeyCode::Char(ch) => prompt
+            .answers()
+            .iter()
+            .find(|answer| answer.key() == ch.to_ascii_lowercase())
+            .copied(),
+        KeyCode::Esc => Some(Answer::Cancel),
+        _ => None,
+    };
+
+    match answer {
+        Some(answer) => answer_prompt(kass, prompt.action, answer, close),
+        None => kass.app.prompt = Some(prompt),
+    }
+
+    Ok(())
+}
+
+fn answer_prompt(kass: &mut Kass, action: PromptAction, answer: Answer, close: &mut bool) {
+    match (action, answer) {
+        (_, Answer::Cancel) => {}
+        (PromptAction::Quit(index), Answer::No) => close_tab(kass, index, close),
+        (PromptAction::Quit(index), _) => {
+            if save_tab(kass, index) {
+                close_tab(kass, index, close);
+            }
+        }
+        (PromptAction::Edit(filepath), Answer::No) => open_file(kass, filepath.as_str()),
+        (PromptAction::Edit(filepath), _) => {
+            if save_tab(kass, kass.app.active_index) {
+                open_file(kass, filepath.as_str());
+            }
+        }
+        (PromptAction::QuitAll(index), Answer::No) => confirm_quit_all(kass, index + 1, close),
+        (PromptAction::QuitAll(index), Answer::Yes) => {
+            if save_tab(kass, index) {
+                confirm_quit_all(kass, index + 1, close);
+            }
+        }
+        // saves the rest without asking, quitting only if all of them could be saved
+        (PromptAction::QuitAll(index), Answer::All) => {
+            let mut saved = true;
+
+            for i in index..kass.app.tabs.len() {
+                if !kass.app.tabs[i].is_saved() {
+                    saved &= save_tab(kass, i);
+                }
+            }
+
+            *close = saved;
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::{collections::HashMap, fmt};
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionValue {
+    Bool(bool),
+    Number(usize),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionKind {
+    Bool,
+    Number,
+    // text limited to the listed values
+    Choice(&'static [&'static str]),
+}
+
+// global options are shared by every tab, buffer options can be overridden per tab
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Global,
+    Buffer,
+}
+
+#[derive(Debug)]
+pub struct OptionDef {
+    pub name: &'static str,
+    pub short: &'static str,
+    pub kind: OptionKind,
+    pub scope: Scope,
+    pub default: &'static str,
+}
+
+pub const OPTIONS: &[OptionDef] = &[
+    OptionDef {
+        name: "number",
+        short: "nu",
+        kind: OptionKind::Bool,
+        scope: Scope::Global,
+        default: "false",
+    },
+    OptionDef {
+        name: "relativenumber",
+        short: "rnu",
+        kind: OptionKind::Bool,
+        scope: Scope::Global,
+        default: "false",
+    },
+    OptionDef {
+        name: "tabstop",
+        short: "ts",
+        kind: OptionKind::Number,
+        scope: Scope::Buffer,
+        default: "4",
+    },
+    // width of one indent level, 0 uses tabstop
+    OptionDef {
+        name: "shiftwidth",
+        short: "sw",
+        kind: OptionKind::Number,
+        scope: Scope::Buffer,
+        default: "0",
+    },
+    // columns Tab inserts and Backspace removes in spaces, 0 uses shiftwidth
+    OptionDef {
+        name: "softtabstop",
+        short: "sts",
+        kind: OptionKind::Number,
+        scope: Scope::Buffer,
+        default: "0",
+    },
+    OptionDef {
+        name: "expandtab",
+        short: "et",
+        kind: OptionKind::Bool,
+        scope: Scope::Buffer,
+        default: "true",
+    },
+    OptionDef {
+        name: "autoindent",
+        short: "ai",
+        kind: OptionKind::Bool,
+        scope: Scope::Buffer,
+        default: "true",
+    },
+    // extra indent after an opening bracket, less when typing the closing one
+    OptionDef {
+        name: "smartindent",
+        short: "si",
+        kind: OptionKind::Bool,
+        scope: Scope::Buffer,
+        default: "true",
+    },
+    // line ending written on save: unix (LF), dos (CRLF) or mac (CR)
+    OptionDef {
+        name: "fileformat",
+        short: "ff",
+        kind: OptionKind::Choice(&["unix", "dos", "mac"]),
+        scope: Scope::Buffer,
+        default: "unix",
+    },
+    OptionDef {
+        name: "fileencoding",
+        short: "fenc",
+        kind: OptionKind::Choice(&["utf-8", "utf-8-bom", "latin1", "utf-16le", "utf-16be"]),
+        scope: Scope::Buffer,
+        default: "utf-8",
+    },
+    OptionDef {
+        name: "trimtrailingwhitespace",
+        short: "trim",
+        kind: OptionKind::Bool,
+        scope: Scope::Buffer,
+        default: "false",
+    },
+    // end the file with a line ending on save
+    OptionDef {
+        name: "fixendofline",
+        short: "fixeol",
+        kind: OptionKind::Bool,
+        scope: Scope::Buffer,
+        default: "false",
+    },
+    // preferred maximum line length, 0 for none
+    OptionDef {
+        name: "textwidth",
+        short: "tw",
+        kind: OptionKind::Number,
+        scope: Scope::Buffer,
+        default: "0",
+    },
+    // type the closer of a bracket or quote along with it, which ones depends on the filetype
+    OptionDef {
+        name: "autopairs",
+        short: "ap",
+        kind: OptionKind::Bool,
+        scope: Scope::Buffer,
+        default: "true",
+    },
+    // rows kept visible above and below the cursor
+    OptionDef {
+        name: "scrolloff",
+        short: "so",
+        kind: OptionKind::Number,
+        scope: Scope::Global,
+        default: "0",
+    },
+    // milliseconds to wait for the rest of a key mapping
+    OptionDef {
+        name: "timeoutlen",
+        short: "tm",
+        kind: OptionKind::Number,
+        scope: Scope::Global,
+        default: "1000",
+    },
+    // command lines kept in the history
+    OptionDef {
+        name: "history",
+        short: "hi",
+        kind: OptionKind::Number,
+        scope: Scope::Global,
+        default: "100",
+    },
+    OptionDef {
+        name: "wrap",
+        short: "wrap",
+        kind: OptionKind::Bool,
+        scope: Scope::Global,
+        default: "false",
+    },
+    OptionDef {
+        name: "ignorecase",
+        short: "ic",
+        kind: OptionKind::Bool,
+        scope: Scope::Global,
+        default: "false",
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static OptionDef> {
+    OPTIONS
+        .iter()
+        .find(|def| def.name == name || def.short == name)
+}
+
+impl OptionDef {
+    pub fn default_value(&self) -> OptionValue {
+        OptionValue::parse(self.kind, self.default).expect("invalid option default")
+    }
+}
+
+impl OptionValue {
+    pub fn parse(kind: OptionKind, input: &str) -> Option<OptionValue> {
+        match kind {
+            OptionKind::Bool => match input {
+                "true" | "on" | "1" => Some(OptionValue::Bool(true)),
+                "false" | "off" | "0" => Some(OptionValue::Bool(false)),
+                _ => None,
+            },
+            OptionKind::Number => input.parse().ok().map(OptionValue::Number),
+            OptionKind::Choice(choices) => choices
+                .contains(&input)
+                .then(|| OptionValue::Text(input.to_string())),
+        }
+    }
+
+    pub fn from_json(kind: OptionKind, value: &Value) -> Option<OptionValue> {
+        match (kind, value) {
+            (OptionKind::Bool, Value::Bool(value)) => Some(OptionValue::Bool(*value)),
+            (OptionKind::Number, Value::Number(value)) => value
+                .as_u64()
+                .map(|value| OptionValue::Number(value as usize)),
+            (_, Value::String(value)) => Self::parse(kind, value),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionValue::Bool(value) => write!(f, "{}", value),
+            OptionValue::Number(value) => write!(f, "{}", value),
+            OptionValue::Text(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    values: HashMap<&'static str, OptionValue>,
+}
+
+impl Options {
+    // every option at its default, used for the global options
+    pub fn with_defaults() -> Options {
+        Options {
+            values: OPTIONS
+                .iter()
+                .map(|def| (def.name, def.default_value()))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OptionValue> {
+        self.values.get(name)
+    }
+
+    pub fn set(&mut self, def: &'static OptionDef, value: OptionValue) {
+        self.values.insert(def.name, value);
+    }
+
+    // the values set in `other` replace these
+    pub fn extend(&mut self, other: &Options) {
+        self.values.extend(
+            other
+                .values
+                .iter()
+                .map(|(name, value)| (*name, value.clone())),
+        );
+    }
+}
+
+// one argument of `:set`
+#[derive(Debug, PartialEq)]
+pub enum SetArg<'a> {
+    Enable(&'a str),
+    Disable(&'a str),
+    Toggle(&'a str),
+    Query(&'a str),
+    Reset(&'a str),
+    Assign(&'a str, &'a str),
+}
+
+impl<'a> SetArg<'a> {
+    pub fn parse(arg: &'a str) -> SetArg<'a> {
+        if let Some((name, value)) = arg.split_once(['=', ':']) {
+            SetArg::Assign(name, value)
+        } else if let Some(name) = arg.strip_suffix('?') {
+            SetArg::Query(name)
+        } else if let Some(name) = arg.strip_suffix('!') {
+            SetArg::Toggle(name)
+        } else if let Some(name) = arg.strip_suffix('&') {
+            SetArg::Reset(name)
+        } else if let Some(name) = arg.strip_prefix("inv").filter(|name| find(name).is_some()) {
+            SetArg::Toggle(name)
+        } else if let Some(name) = arg.strip_prefix("no").filter(|name| find(name).is_some()) {
+            SetArg::Disable(name)
+        } else {
+            SetArg::Enable(arg)
+        }
+    }
+}
+
+// how `:set` shows an option, `wrap`/`nowrap` for booleans and `name=value` otherwise
+pub fn describe(def: &OptionDef, value: &OptionValue) -> String {
+    match value {
+        OptionValue::Bool(true) => def.name.to_string(),
+        OptionValue::Bool(false) => format!("no{}", def.name),
+        value => format!("{}={}", def.name, value),
+    }
+}
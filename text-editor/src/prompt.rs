@@ -0,0 +1,62 @@
+// what a yes/no question in the command line was asked for, done once it is answered
+#[derive(Debug, Clone)]
+pub enum PromptAction {
+    // closing the tab at this index
+    Quit(usize),
+    // closing every tab, asking about the unsaved tab at this index and then the ones after it
+    QuitAll(usize),
+    // opening this file in place of the active tab
+    Edit(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Answer {
+    Yes,
+    No,
+    All,
+    Cancel,
+}
+
+impl Answer {
+    pub fn key(&self) -> char {
+        match self {
+            Answer::Yes => 'y',
+            Answer::No => 'n',
+            Answer::All => 'a',
+            Answer::Cancel => 'c',
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub message: String,
+    pub action: PromptAction,
+}
+
+impl Prompt {
+    pub fn new(message: &str, action: PromptAction) -> Prompt {
+        Prompt {
+            message: message.to_string(),
+            action,
+        }
+    }
+
+    pub fn answers(&self) -> &'static [Answer] {
+        match self.action {
+            PromptAction::QuitAll(_) => &[Answer::Yes, Answer::No, Answer::All, Answer::Cancel],
+            _ => &[Answer::Yes, Answer::No, Answer::Cancel],
+        }
+    }
+
+    // `Save changes to foo.txt? [y/n/c]`
+    pub fn text(&self) -> String {
+        let keys: Vec<String> = self
+            .answers()
+            .iter()
+            .map(|answer| answer.key().to_string())
+            .collect();
+
+        format!("{} [{}]", self.message, keys.join("/"))
+    }
+}
@@ -0,0 +1,84 @@
+// lines a command works on, counted from 1 with both ends included; `:0r` uses line 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    pub fn line(line: usize) -> LineRange {
+        LineRange {
+            start: line,
+            end: line,
+        }
+    }
+}
+
+// `%`, `N`, `.`, `$` with `+N`/`-N` offsets and `A,B` between two addresses, before a command.
+// `current` and `last` are the cursor line and the line count.
+pub fn parse(text: &str, current: usize, last: usize) -> (Option<LineRange>, &str) {
+    if let Some(rest) = text.strip_prefix('%') {
+        return (
+            Some(LineRange {
+                start: 1,
+                end: last,
+            }),
+            rest,
+        );
+    }
+
+    let (start, rest) = parse_address(text, current, last);
+
+    match rest.strip_prefix(',') {
+        Some(after) => {
+            let (end, rest) = parse_address(after, current, last);
+            let start = start.unwrap_or(current);
+            let end = end.unwrap_or(current);
+
+            // a backwards range is turned around
+            let range = LineRange {
+                start: start.min(end),
+                end: start.max(end),
+            };
+            (Some(range), rest)
+        }
+        None => (start.map(LineRange::line), rest),
+    }
+}
+
+fn parse_address(text: &str, current: usize, last: usize) -> (Option<usize>, &str) {
+    let digits = text.len()
+        - text
+            .trim_start_matches(|ch: char| ch.is_ascii_digit())
+            .len();
+
+    let (mut line, mut rest) = if digits > 0 {
+        (text[..digits].parse().ok(), &text[digits..])
+    } else if let Some(rest) = text.strip_prefix('.') {
+        (Some(current), rest)
+    } else if let Some(rest) = text.strip_prefix('$') {
+        (Some(last), rest)
+    } else {
+        (None, text)
+    };
+
+    // `+3` alone is relative to the cursor line, `+` alone means 1
+    while let Some(sign) = rest.chars().next().filter(|ch| *ch == '+' || *ch == '-') {
+        let after = &rest[1..];
+        let digits = after.len()
+            - after
+                .trim_start_matches(|ch: char| ch.is_ascii_digit())
+                .len();
+        let offset: usize = after[..digits].parse().unwrap_or(1);
+        let base = line.unwrap_or(current);
+
+        line = Some(if sign == '+' {
+            base + offset
+        } else {
+            base.saturating_sub(offset)
+        });
+        rest = &after[digits..];
+    }
+
+    (line, rest)
+}
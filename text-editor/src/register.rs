@@ -0,0 +1,34 @@
+// text deleted or yanked in normal mode, put back with `p`/`P`
+#[derive(Debug, Clone, Default)]
+pub struct Register {
+    pub text: Vec<String>,
+    // whole lines rather than part of one
+    pub linewise: bool,
+}
+
+impl Register {
+    pub fn lines(text: Vec<String>) -> Register {
+        Register {
+            text,
+            linewise: true,
+        }
+    }
+
+    pub fn chars(text: String) -> Register {
+        Register {
+            text: vec![text],
+            linewise: false,
+        }
+    }
+
+    // as typed text, whole lines ending in a line break
+    pub fn as_text(&self) -> String {
+        let text = self.text.join("\n");
+
+        if self.linewise {
+            text + "\n"
+        } else {
+            text
+        }
+    }
+}
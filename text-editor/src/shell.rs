@@ -0,0 +1,56 @@
+use std::{
+    io::{Result, Write},
+    process::{Command, ExitStatus, Output, Stdio},
+    thread,
+};
+
+// `sh -c` or `cmd /C` with the command line
+fn command(line: &str) -> Command {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+
+    command.arg(line);
+    command
+}
+
+// runs `line` in the shell with `input` on its stdin and collects what it prints
+pub fn run(line: &str, input: Option<String>) -> Result<Output> {
+    let mut child = command(line)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // written from another thread so a command that prints while reading can't block on us
+    let writer = match (child.stdin.take(), input) {
+        (Some(mut stdin), Some(input)) => {
+            Some(thread::spawn(move || stdin.write_all(input.as_bytes())))
+        }
+        _ => None,
+    };
+
+    let output = child.wait_with_output()?;
+
+    if let Some(writer) = writer {
+        // a command that exits without reading all of it is not an error
+        let _ = writer.join();
+    }
+
+    Ok(output)
+}
+
+// runs `line` on the terminal itself, for commands that print or ask for input
+pub fn run_in_terminal(line: &str) -> Result<ExitStatus> {
+    command(line).status()
+}
@@ -0,0 +1,332 @@
+use std::ops::Range;
+
+// what `iw`, `a(`, `it` and the others cover: byte offsets in the buffer's lines joined with `\n`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextObject {
+    pub range: Range<usize>,
+    // whole lines, as `ip`/`ap` are
+    pub linewise: bool,
+}
+
+impl TextObject {
+    fn chars(range: Range<usize>) -> Option<TextObject> {
+        Some(TextObject {
+            range,
+            linewise: false,
+        })
+    }
+}
+
+// the object named by `name` around byte `cursor` of `text`, `around` for `a` and not for `i`
+pub fn find(text: &str, cursor: usize, name: char, around: bool) -> Option<TextObject> {
+    match name {
+        'w' => word(text, cursor, around),
+        's' => sentence(text, cursor, around),
+        'p' => paragraph(text, cursor, around),
+        '"' | '\'' | '`' => quote(text, cursor, name, around),
+        '(' | ')' | 'b' => bracket(text, cursor, '(', ')', around),
+        '{' | '}' | 'B' => bracket(text, cursor, '{', '}', around),
+        '[' | ']' => bracket(text, cursor, '[', ']', around),
+        '<' | '>' => bracket(text, cursor, '<', '>', around),
+        't' => tag(text, cursor, around),
+        _ => None,
+    }
+}
+
+#[derive(PartialEq)]
+enum Class {
+    Blank,
+    Word,
+    Other,
+}
+
+fn class(ch: char) -> Class {
+    if ch.is_whitespace() {
+        Class::Blank
+    } else if ch.is_alphanumeric() || ch == '_' {
+        Class::Word
+    } else {
+        Class::Other
+    }
+}
+
+fn char_at(text: &str, i: usize) -> Option<char> {
+    text.get(i..).and_then(|rest| rest.chars().next())
+}
+
+// the run of characters around `i` that `keep` accepts, never past a line end
+fn run(text: &str, i: usize, keep: impl Fn(char) -> bool) -> Range<usize> {
+    let start = text[..i]
+        .char_indices()
+        .rev()
+        .take_while(|(_, ch)| *ch != '\n' && keep(*ch))
+        .last()
+        .map(|(j, _)| j)
+        .unwrap_or(i);
+    let end = text[i..]
+        .char_indices()
+        .find(|(_, ch)| *ch == '\n' || !keep(*ch))
+        .map(|(j, _)| i + j)
+        .unwrap_or(text.len());
+
+    start..end
+}
+
+fn is_blank(ch: char) -> bool {
+    ch != '\n' && ch.is_whitespace()
+}
+
+// blanks after `range`, or before it when there are none after
+fn with_blanks(text: &str, range: Range<usize>) -> Range<usize> {
+    let after = run(text, range.end, is_blank);
+
+    if after.end > range.end && after.start == range.end {
+        range.start..after.end
+    } else {
+        let before = run(text, range.start, is_blank);
+        before.start.min(range.start)..range.end
+    }
+}
+
+fn word(text: &str, cursor: usize, around: bool) -> Option<TextObject> {
+    let ch = char_at(text, cursor).filter(|ch| *ch != '\n')?;
+    let kind = class(ch);
+    let range = run(text, cursor, |c| class(c) == kind);
+
+    if !around {
+        return TextObject::chars(range);
+    }
+
+    // on blanks `aw` takes the word after them
+    if kind == Class::Blank {
+        let next = char_at(text, range.end).filter(|ch| *ch != '\n');
+        let end = match next {
+            Some(next) => run(text, range.end, |c| class(c) == class(next)).end,
+            None => range.end,
+        };
+        TextObject::chars(range.start..end)
+    } else {
+        TextObject::chars(with_blanks(text, range))
+    }
+}
+
+// start and end of the line `cursor` is on, without the `\n`
+fn line_bounds(text: &str, cursor: usize) -> Range<usize> {
+    let start = text[..cursor].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let end = text[cursor..]
+        .find('\n')
+        .map(|i| cursor + i)
+        .unwrap_or(text.len());
+
+    start..end
+}
+
+fn lines_with_bounds(text: &str) -> Vec<Range<usize>> {
+    let mut start = 0;
+
+    text.split('\n')
+        .map(|line| {
+            let range = start..start + line.len();
+            start = range.end + 1;
+            range
+        })
+        .collect()
+}
+
+fn paragraph(text: &str, cursor: usize, around: bool) -> Option<TextObject> {
+    let lines = lines_with_bounds(text);
+    let blank = |i: usize| text[lines[i].clone()].trim().is_empty();
+    let row = lines.iter().position(|line| line.end >= cursor)?;
+
+    let on_blank = blank(row);
+    let block = |from: usize, wanted: bool| -> (usize, usize) {
+        let mut first = from;
+        while first > 0 && blank(first - 1) == wanted {
+            first -= 1;
+        }
+        let mut last = from;
+        while last + 1 < lines.len() && blank(last + 1) == wanted {
+            last += 1;
+        }
+        (first, last)
+    };
+
+    let (mut first, mut last) = block(row, on_blank);
+
+    if around {
+        if last + 1 < lines.len() {
+            last = block(last + 1, !on_blank).1;
+        } else if first > 0 && !on_blank {
+            first = block(first - 1, true).0;
+        }
+    }
+
+    Some(TextObject {
+        range: lines[first].start..lines[last].end,
+        linewise: true,
+    })
+}
+
+fn sentence(text: &str, cursor: usize, around: bool) -> Option<TextObject> {
+    // sentences don't cross a blank line
+    let lines = lines_with_bounds(text);
+    let row = lines.iter().position(|line| line.end >= cursor)?;
+    let blank = |i: usize| text[lines[i].clone()].trim().is_empty();
+
+    if blank(row) {
+        return None;
+    }
+
+    let mut first = row;
+    while first > 0 && !blank(first - 1) {
+        first -= 1;
+    }
+    let mut last = row;
+    while last + 1 < lines.len() && !blank(last + 1) {
+        last += 1;
+    }
+    let paragraph = lines[first].start..lines[last].end;
+
+    // a sentence ends after `.`, `!` or `?` (and closing quotes or brackets) followed by a blank
+    let mut starts = vec![paragraph.start];
+    let chars: Vec<(usize, char)> = text[paragraph.clone()]
+        .char_indices()
+        .map(|(i, ch)| (paragraph.start + i, ch))
+        .collect();
+
+    for (k, (_, ch)) in chars.iter().enumerate() {
+        if !matches!(ch, '.' | '!' | '?') {
+            continue;
+        }
+
+        let mut next = k + 1;
+        while next < chars.len() && matches!(chars[next].1, ')' | ']' | '"' | '\'') {
+            next += 1;
+        }
+        if next < chars.len() && chars[next].1.is_whitespace() {
+            while next < chars.len() && chars[next].1.is_whitespace() {
+                next += 1;
+            }
+            if next < chars.len() {
+                starts.push(chars[next].0);
+            }
+        }
+    }
+
+    let index = starts.iter().rposition(|start| *start <= cursor)?;
+    let start = starts[index];
+    let end = starts.get(index + 1).copied().unwrap_or(paragraph.end);
+    let trimmed = start + text[start..end].trim_end().len();
+
+    TextObject::chars(start..if around { end } else { trimmed })
+}
+
+fn quote(text: &str, cursor: usize, quote: char, around: bool) -> Option<TextObject> {
+    let line = line_bounds(text, cursor);
+
+    let mut escaped = false;
+    let mut quotes = vec![];
+    for (i, ch) in text[line.clone()].char_indices() {
+        if ch == quote && !escaped {
+            quotes.push(line.start + i);
+        }
+        escaped = ch == '\\' && !escaped;
+    }
+
+    // the pair the cursor is in, or else the first one after it
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(_, close)| cursor <= *close)?;
+
+    if around {
+        TextObject::chars(with_blanks(text, open..close + 1))
+    } else {
+        TextObject::chars(open + 1..close)
+    }
+}
+
+fn bracket(text: &str, cursor: usize, open: char, close: char, around: bool) -> Option<TextObject> {
+    // the opener enclosing the cursor, or the one a closer under the cursor belongs to
+    let mut depth = 0;
+    let mut start = None;
+    for (i, ch) in text[..cursor + char_at(text, cursor).map_or(0, char::len_utf8)]
+        .char_indices()
+        .rev()
+    {
+        if ch == close && i != cursor {
+            depth += 1;
+        } else if ch == open {
+            if depth == 0 {
+                start = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let start = start?;
+
+    let mut depth = 0;
+    let mut end = None;
+    for (i, ch) in text[start + open.len_utf8()..].char_indices() {
+        let i = start + open.len_utf8() + i;
+        if ch == open {
+            depth += 1;
+        } else if ch == close {
+            if depth == 0 {
+                end = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let end = end?;
+
+    if around {
+        TextObject::chars(start..end + close.len_utf8())
+    } else {
+        TextObject::chars(start + open.len_utf8()..end)
+    }
+}
+
+fn tag(text: &str, cursor: usize, around: bool) -> Option<TextObject> {
+    // (name, start of the opening tag, end of it) for tags not closed yet
+    let mut open: Vec<(String, usize, usize)> = vec![];
+    let mut best: Option<(Range<usize>, Range<usize>)> = None;
+    let mut i = 0;
+
+    while let Some(lt) = text[i..].find('<').map(|j| i + j) {
+        let gt = match text[lt..].find('>') {
+            Some(j) => lt + j,
+            None => break,
+        };
+        let inner = &text[lt + 1..gt];
+        i = gt + 1;
+
+        if let Some(name) = inner.strip_prefix('/') {
+            let name = name.trim();
+
+            if let Some(k) = open.iter().rposition(|(n, _, _)| n == name) {
+                let (_, start, open_end) = open[k].clone();
+                open.truncate(k);
+
+                // the smallest pair around the cursor
+                let outer = start..gt + 1;
+                if outer.contains(&cursor)
+                    && best.as_ref().is_none_or(|(b, _)| outer.len() < b.len())
+                {
+                    best = Some((outer, open_end..lt));
+                }
+            }
+        } else if !inner.ends_with('/') && !inner.starts_with(['!', '?']) {
+            let name: String = inner.chars().take_while(|ch| !ch.is_whitespace()).collect();
+
+            if !name.is_empty() {
+                open.push((name, lt, gt + 1));
+            }
+        }
+    }
+
+    let (outer, inner) = best?;
+    TextObject::chars(if around { outer } else { inner })
+}
@@ -10,9 +10,14 @@ use tui::{
     Frame,
 };
 
-use crate::{editor::Bound, enums::*, kass::Kass};
+use crate::{
+    completion::Completion,
+    editor::{display_width, expand_tabs, Bound},
+    enums::*,
+    kass::Kass,
+};
 
-fn command_ui(kass: &mut Kass) -> Paragraph {
+fn command_ui(kass: &mut Kass) -> Paragraph<'_> {
     let command_paragraph = Paragraph::new(Text::from(Spans::from(kass.app.command.clone())));
     let error_paragraph = Paragraph::new(Text::from(Spans::from(Span::styled(
         kass.app.error.clone(),
@@ -23,6 +28,13 @@ fn command_ui(kass: &mut Kass) -> Paragraph {
     ))));
     let info_paragraph = Paragraph::new(Text::from(kass.app.info.clone()));
 
+    if let Some(prompt) = &kass.app.prompt {
+        return Paragraph::new(Text::from(Spans::from(Span::styled(
+            prompt.text(),
+            Style::default().add_modifier(Modifier::BOLD),
+        ))));
+    }
+
     match kass.app.action {
         CommandAction::Command => command_paragraph,
         CommandAction::Info => info_paragraph,
@@ -30,12 +42,20 @@ fn command_ui(kass: &mut Kass) -> Paragraph {
     }
 }
 
-fn statusline_ui(kass: &mut Kass) -> Paragraph {
+fn statusline_ui(kass: &mut Kass) -> Paragraph<'_> {
     let filepath = kass.app.tabs[kass.app.active_index].filepath.as_str();
 
     let filepath_span = Span::styled(filepath, Style::default().fg(Color::Black));
 
     let (statusline_span, style) = match kass.app.mode {
+        Mode::Normal if kass.app.tabs[kass.app.active_index].block_start.is_some() => (
+            vec![
+                Span::styled("Visual Block", Style::default().fg(Color::Blue)),
+                Span::raw("    "),
+                filepath_span,
+            ],
+            Style::default(),
+        ),
         Mode::Normal => (
             vec![
                 Span::styled("Normal", Style::default().fg(Color::Yellow)),
@@ -52,15 +72,70 @@ fn statusline_ui(kass: &mut Kass) -> Paragraph {
             vec![Span::raw("Command"), Span::raw("    "), filepath_span],
             Style::default(),
         ),
+        Mode::Replace => (
+            vec![
+                Span::styled("Replace", Style::default().fg(Color::Red)),
+                Span::raw("    "),
+                filepath_span,
+            ],
+            Style::default(),
+        ),
     };
 
+    let mut statusline_span = statusline_span;
+    if let Some(recording) = &kass.macro_recording {
+        statusline_span.push(Span::raw(format!("    recording @{}", recording.register)));
+    }
+
     let mut statusline_text = Text::from(Spans::from(statusline_span));
     statusline_text.patch_style(style);
 
     Paragraph::new(statusline_text).style(Style::default().bg(Color::DarkGray))
 }
 
-fn tabs_ui(kass: &mut Kass) -> Tabs {
+// command-line completions in place of the statusline, scrolled to keep the selected one in view
+fn wildmenu_ui(completion: &Completion, width: usize) -> Paragraph<'_> {
+    let selected = completion.selected.unwrap_or(0);
+    let widths: Vec<usize> = completion
+        .candidates
+        .iter()
+        .map(|candidate| candidate.chars().count() + 2)
+        .collect();
+
+    let mut first = 0;
+    while first < selected && widths[first..=selected].iter().sum::<usize>() + 3 > width {
+        first += 1;
+    }
+
+    let mut spans = vec![];
+    let mut used = 0;
+
+    if first > 0 {
+        spans.push(Span::raw("< "));
+        used += 2;
+    }
+
+    for (i, candidate) in completion.candidates.iter().enumerate().skip(first) {
+        if used + widths[i] > width {
+            spans.push(Span::raw(">"));
+            break;
+        }
+
+        let style = if completion.selected == Some(i) {
+            Style::default().bg(Color::Yellow).fg(Color::Black)
+        } else {
+            Style::default()
+        };
+
+        spans.push(Span::styled(candidate.as_str(), style));
+        spans.push(Span::raw("  "));
+        used += widths[i];
+    }
+
+    Paragraph::new(Text::from(Spans::from(spans))).style(Style::default().bg(Color::DarkGray))
+}
+
+fn tabs_ui(kass: &mut Kass) -> Tabs<'_> {
     let tab_titles = kass
         .app
         .tabs
@@ -86,7 +161,30 @@ fn tabs_ui(kass: &mut Kass) -> Tabs {
         )
 }
 
-fn editor_ui(kass: &mut Kass) -> (List, List) {
+// a row with some of its cells styled, such as extra cursors, one past the end is a space
+fn styled_cells(row: String, cells: &[(usize, Style)]) -> Spans<'static> {
+    if cells.is_empty() {
+        return Spans::from(Span::raw(row));
+    }
+
+    let mut spans = vec![];
+    let mut text = String::new();
+    let width = row.chars().count();
+
+    for (cell, ch) in row.chars().chain([' ']).enumerate() {
+        if let Some((_, style)) = cells.iter().find(|(styled, _)| *styled == cell) {
+            spans.push(Span::raw(std::mem::take(&mut text)));
+            spans.push(Span::styled(ch.to_string(), *style));
+        } else if cell < width {
+            text.push(ch);
+        }
+    }
+    spans.push(Span::raw(text));
+
+    Spans::from(spans)
+}
+
+fn editor_ui(kass: &mut Kass) -> (List<'_>, List<'_>) {
     // let (editor_width, editor_height) = kass.app.tabs[kass.app.active_index].boundary(terminal_width, terminal_height);
     let editor_width = kass.app.tabs[kass.app.active_index].editor_size.x;
     let editor_height = kass.app.tabs[kass.app.active_index].editor_size.y;
@@ -121,17 +219,47 @@ fn editor_ui(kass: &mut Kass) -> (List, List) {
         }
     }
 
+    let tabstop = kass.option_number("tabstop");
+    let rowoff = kass.app.tabs[kass.app.active_index].rowoff as usize;
+    let tab = &kass.app.tabs[kass.app.active_index];
+    let normal = kass.app.mode == Mode::Normal;
+
+    let cursor_style = Style::default().add_modifier(Modifier::REVERSED);
+    let mut styled: Vec<(usize, usize, Style)> = tab
+        .extra_cursor_cells(normal, tabstop)
+        .into_iter()
+        .map(|(row, cell)| (row, cell, cursor_style))
+        .collect();
+
+    let block_style = Style::default().bg(Color::Blue);
+    styled.extend(
+        tab.block_cells(tabstop)
+            .into_iter()
+            .map(|(row, cell)| (row, cell, block_style)),
+    );
+
+    // the bracket pairing with the one at the cursor
+    if let Some((row, col)) = tab.matching_bracket(!normal) {
+        let cell = display_width(&tab.rows[row][..col], tabstop);
+        styled.push((row, cell, Style::default().bg(Color::DarkGray)));
+    }
+
     // for displaying content of the editor
     let rows: Vec<ListItem> = new_rows
         .iter()
         .enumerate()
-        .map(|(_i, m)| {
-            let content = vec![Spans::from(Span::raw(format!("{}", m)))];
+        .map(|(i, m)| {
+            let cells: Vec<(usize, Style)> = styled
+                .iter()
+                .filter(|(row, _, _)| *row == i + rowoff)
+                .map(|(_, cell, style)| (*cell, *style))
+                .collect();
+            let content = vec![styled_cells(expand_tabs(m, tabstop), &cells)];
             ListItem::new(content)
         })
         .collect();
 
-    let line_numbers: Vec<ListItem> = match kass.line_number {
+    let line_numbers: Vec<ListItem> = match kass.line_number() {
         LineNumber::Absolute => new_rows
             .iter()
             .enumerate()
@@ -178,14 +306,18 @@ fn editor_ui(kass: &mut Kass) -> (List, List) {
             .collect(),
     };
 
-    // update cursor
-    kass.cursor.set_pos(
-        kass.app.tabs[kass.app.active_index].cursor.x,
-        kass.app.tabs[kass.app.active_index].cursor.y,
+    // update cursor, in screen columns so tabs count as their full width
+    let cursor = kass.app.tabs[kass.app.active_index].cursor;
+    let cursor_row = &kass.app.tabs[kass.app.active_index].rows
+        [(cursor.y + kass.app.tabs[kass.app.active_index].rowoff) as usize];
+    let cursor_col = display_width(
+        cursor_row.get(..cursor.x as usize).unwrap_or(cursor_row),
+        tabstop,
     );
+    kass.cursor.set_pos(cursor_col as u16, cursor.y);
 
     (
-        List::new(rows).block(Block::default().borders(match kass.line_number {
+        List::new(rows).block(Block::default().borders(match kass.line_number() {
             LineNumber::None => Borders::ALL,
             _ => Borders::RIGHT | Borders::TOP | Borders::BOTTOM,
         })), // rows
@@ -207,7 +339,12 @@ pub fn ui<B: Backend>(kass: &mut Kass, frame: &mut Frame<B>) {
         )
         .split(frame.size());
 
-    frame.render_widget(statusline_ui(kass), chunks[2]);
+    match &kass.app.completion {
+        Some(completion) if completion.candidates.len() > 1 => {
+            frame.render_widget(wildmenu_ui(completion, chunks[2].width as usize), chunks[2])
+        }
+        _ => frame.render_widget(statusline_ui(kass), chunks[2]),
+    }
     frame.render_widget(command_ui(kass), chunks[3]);
     frame.render_widget(tabs_ui(kass), chunks[0]);
 
@@ -218,7 +355,7 @@ pub fn ui<B: Backend>(kass: &mut Kass, frame: &mut Frame<B>) {
         .margin(0)
         .direction(tui::layout::Direction::Horizontal)
         .constraints([
-            Constraint::Length(match kass.line_number {
+            Constraint::Length(match kass.line_number() {
                 LineNumber::None => 0,
                 _ => 6,
             }),
@@ -231,6 +368,8 @@ pub fn ui<B: Backend>(kass: &mut Kass, frame: &mut Frame<B>) {
     let editor_width = editor_chunk[1].width;
     kass.editor_size = (editor_width, editor_height);
     kass.app.tabs[kass.app.active_index].boundary(editor_width, editor_height); //setting height and width
+    let scrolloff = kass.option_number("scrolloff");
+    kass.app.tabs[kass.app.active_index].keep_margin(scrolloff);
 
     let (rows, line_numbers) = editor_ui(kass);
 
@@ -242,22 +381,31 @@ pub fn ui<B: Backend>(kass: &mut Kass, frame: &mut Frame<B>) {
         Mode::Insert => {
             execute!(stdout(), SetCursorStyle::BlinkingBar).expect("Couldn't enable blinking")
         }
+        Mode::Replace => execute!(stdout(), SetCursorStyle::BlinkingUnderScore)
+            .expect("Couldn't enable blinking"),
         _ => execute!(stdout(), SetCursorStyle::SteadyBlock).expect("Couldn't disable blinking"),
     }
 
+    if let Some(prompt) = &kass.app.prompt {
+        let width = prompt.text().chars().count() + 1;
+        frame.set_cursor(chunks[3].x + width as u16, chunks[3].y);
+        return;
+    }
+
     match kass.app.mode {
         Mode::Command => {
-            frame.set_cursor(chunks[2].x + kass.app.command.len() as u16, chunks[2].y + 1)
+            let cursor = kass.app.command[..kass.app.command_cursor].chars().count();
+            frame.set_cursor(chunks[2].x + cursor as u16, chunks[2].y + 1)
         }
 
         Mode::Normal => frame.set_cursor(
             if kass.cursor.x == 0 {
-                match kass.line_number {
+                match kass.line_number() {
                     LineNumber::None => editor_chunk[1].x + 1,
                     _ => editor_chunk[1].x,
                 }
             } else {
-                match kass.line_number {
+                match kass.line_number() {
                     LineNumber::None => editor_chunk[1].x + kass.cursor.x,
                     _ => editor_chunk[1].x + kass.cursor.x - 1,
                 }
@@ -268,7 +416,7 @@ pub fn ui<B: Backend>(kass: &mut Kass, frame: &mut Frame<B>) {
         _ => frame.set_cursor(
             editor_chunk[1].x
                 + kass.cursor.x
-                + match kass.line_number {
+                + match kass.line_number() {
                     LineNumber::None => 1,
                     _ => 0,
                 },